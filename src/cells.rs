@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use image::RgbaImage;
+use serde::{Serialize, Deserialize};
+
+use crate::legend::Legend;
+use crate::types::{
+	Color,
+	Point,
+	Warning,
+};
+
+/// Changes of colors at cell borders required to accept a detected cell size.
+const DETECTION_RATIO: f64 = 0.9;
+
+/// Largest cell size tried by the detection.
+const MAX_DETECTED_SIZE: u32 = 256;
+
+/// Size of square blocks of pixels drawn for a single tile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CellSize
+{
+	Fixed(u32),
+	/// The largest size most color changes of the image are aligned to.
+	Auto,
+}
+
+impl Default for CellSize
+{
+	fn default() -> Self {
+		CellSize::Fixed(1)
+	}
+}
+
+impl CellSize
+{
+	/// Parses `auto` or a size in pixels.
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"auto" | "Auto" => Some(CellSize::Auto),
+			_ => name.parse().ok().filter(|size| *size > 0).map(CellSize::Fixed),
+		}
+	}
+
+	pub fn resolve(self, img: &RgbaImage, legend: &Legend) -> u32 {
+		match self {
+			CellSize::Fixed(size) => size.max(1),
+			CellSize::Auto => detect_cell_size(img, legend),
+		}
+	}
+}
+
+/// Largest cell size at which most colors change on cell borders, `1` if there is no such size.
+pub fn detect_cell_size(img: &RgbaImage, legend: &Legend) -> u32 {
+	detect_common_cell_size(&[img], legend)
+}
+
+/// Cell size detected from the color changes of all images together, so images with few changes
+/// get the size of the others.
+pub fn detect_common_cell_size(images: &[&RgbaImage], legend: &Legend) -> u32 {
+	// counts of color changes between neighbour pixels by their position, along both axes
+	let side = images.iter().map(|img| img.width().max(img.height())).max().unwrap_or(0);
+	let mut changes = vec![0u64; side as usize];
+	let mut max_size = MAX_DETECTED_SIZE;
+
+	for img in images {
+		let (width, height) = img.dimensions();
+		let color = |x: u32, y: u32| legend.get_pixel_color(&img.get_pixel(x, y).0);
+
+		for y in 0 .. height {
+			for x in 0 .. width {
+				if x > 0 && color(x - 1, y) != color(x, y) {
+					changes[x as usize] += 1;
+				}
+
+				if y > 0 && color(x, y - 1) != color(x, y) {
+					changes[y as usize] += 1;
+				}
+			}
+		}
+
+		max_size = max_size.min(width.max(height) / 2);
+	}
+
+	let total: u64 = changes.iter().sum();
+	if total == 0 {
+		return 1;
+	}
+
+	(2 ..= max_size).rev()
+		.find(|size| {
+			let aligned: u64 = changes.iter().step_by(*size as usize).sum();
+			aligned as f64 >= total as f64 * DETECTION_RATIO
+		})
+		.unwrap_or(1)
+}
+
+/// One pixel per `size` × `size` cell taking the most frequent color of the cell, empty pixels included.
+/// Ties are won by the color met first. Cells of several colors are reported as warnings.
+pub fn downsample(img: &RgbaImage, size: u32, legend: &Legend, warnings: &mut Vec<Warning>) -> RgbaImage {
+	let (width, height) = img.dimensions();
+	let (cells_width, cells_height) = (width.div_ceil(size), height.div_ceil(size));
+
+	let mut cells = RgbaImage::new(cells_width, cells_height);
+
+	for cy in 0 .. cells_height {
+		for cx in 0 .. cells_width {
+			// pixels of every color by their first pixel
+			let mut votes: Vec<(Option<Color>, image::Rgba<u8>, usize)> = Vec::new();
+			let mut indices: HashMap<Option<Color>, usize> = HashMap::new();
+
+			for y in cy * size .. ((cy + 1) * size).min(height) {
+				for x in cx * size .. ((cx + 1) * size).min(width) {
+					let pixel = *img.get_pixel(x, y);
+					let color = legend.get_pixel_color(&pixel.0);
+
+					let index = *indices.entry(color).or_insert_with(|| {
+						votes.push((color, pixel, 0));
+						votes.len() - 1
+					});
+
+					votes[index].2 += 1;
+				}
+			}
+
+			let winner = votes.iter()
+				.rev()
+				.max_by_key(|(_, _, count)| *count)
+				.unwrap();
+
+			cells.put_pixel(cx, cy, winner.1);
+
+			if votes.len() > 1 {
+				warnings.push(Warning::MixedCell { cell: Point::new(cx, cy), colors_count: votes.len(), color: winner.0 });
+			}
+		}
+	}
+
+	cells
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use layouter::{CellSize, Color, ColorSpace, Connectivity, Decomposition, PaletteSource, PixelAnchor, SvgOptions, TiledComplex, WorldPoint};
+
+pub const USAGE: &str = "\
+Usage: layouter [OPTIONS] <INPUT>...
+
+Converts level-layout images into level files.
+
+Arguments:
+  <INPUT>...             Layout images to convert, or none if layers are given.
+                         Use `-` to read a single image from stdin
+
+Options:
+  -o, --output <PATH>    Output file for a single input, or output directory for several inputs.
+                         Use `-` to write a single layout to stdout.
+                         Defaults to the input path with the format's extension,
+                         or to stdout for an image read from stdin
+  -f, --format <FORMAT>  Output format: ron, json, tmx, tmj, ldtk, svg [default: ron]
+  -L, --layer <NAME=IMAGE>
+                         Image of a level layer, may be repeated. All layers are converted
+                         into one layered level written next to the first image
+  -l, --legend <PATH>    RON legend naming the layout colors
+  -d, --decomposition <STRATEGY>
+                         How complex shapes are split into boxes, overrides the legend:
+                         tallest-column, row-merge, minimum-rectangles [default: tallest-column]
+  -c, --connectivity <4|8>
+                         Group pixels touching by a side (4) or by a corner too (8),
+                         overrides the legend [default: 4]
+  -b, --background <COLOR>
+                         Color of empty pixels as `#RRGGBB`, may be repeated,
+                         overrides the legend [default: #FFFFFF]
+      --alpha-threshold <ALPHA>
+                         Pixels with a lower alpha are empty, overrides the legend [default: 255]
+      --keep-alpha       Keep alpha of pixels in layout colors
+      --cell-size <SIZE|auto>
+                         Take every SIZE × SIZE block of pixels as one tile, or detect the size
+                         of blocks, overrides the legend [default: 1]
+  -s, --snap <TOLERANCE> Snap pixel colors to the palette within the distance
+      --palette <legend|COUNT>
+                         Snap to legend colors or to COUNT colors clustered from the image,
+                         implies snapping [default: legend]
+      --color-space <rgb|lab>
+                         Space colors are compared in when snapping, implies snapping [default: rgb]
+      --scale <UNITS>    World units per layout pixel, writes the layout in world coordinates
+      --origin <X,Y>     World position of the layout top-left corner, or bottom-left one
+                         with --flip-y, writes the layout in world coordinates
+      --flip-y           World y points up, writes the layout in world coordinates
+      --anchor <corner|center>
+                         Position of single pixels in world coordinates [default: corner]
+      --tiled-complex <polygon|rects>
+                         How complex shapes are written to Tiled maps [default: polygon]
+      --svg-overlays <LIST>
+                         Comma-separated overlays of SVG pictures: outlines, holes,
+                         dependencies or all
+      --svg-scale <SIZE> Size of a layout pixel in SVG pictures [default: 16]
+  -v, --verbose          Print details about every processed layout
+  -q, --quiet            Print errors only
+  -h, --help             Print this help
+  -V, --version          Print version";
+
+/// Exit code of a failed conversion.
+pub const EXIT_FAILURE: u8 = 1;
+/// Exit code of invalid command-line arguments.
+pub const EXIT_USAGE: u8 = 2;
+
+/// Path of an input image read from stdin.
+pub const STDIN: &str = "-";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat
+{
+	Ron,
+	Json,
+	/// Tiled map as XML.
+	Tmx,
+	/// Tiled map as JSON.
+	Tmj,
+	/// LDtk project.
+	Ldtk,
+	/// Debug picture of the shapes.
+	Svg,
+}
+
+impl OutputFormat
+{
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name.to_ascii_lowercase().as_str() {
+			"ron" => Some(OutputFormat::Ron),
+			"json" => Some(OutputFormat::Json),
+			"tmx" => Some(OutputFormat::Tmx),
+			"tmj" => Some(OutputFormat::Tmj),
+			"ldtk" => Some(OutputFormat::Ldtk),
+			"svg" => Some(OutputFormat::Svg),
+			_ => None,
+		}
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			OutputFormat::Ron => "ron",
+			OutputFormat::Json => "json",
+			OutputFormat::Tmx => "tmx",
+			OutputFormat::Tmj => "tmj",
+			OutputFormat::Ldtk => "ldtk",
+			OutputFormat::Svg => "svg",
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Verbosity
+{
+	Quiet,
+	Normal,
+	Verbose,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Output
+{
+	Stdout,
+	File(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct Args
+{
+	pub inputs: Vec<PathBuf>,
+	/// Images of a layered level by the layer names.
+	pub layers: Vec<(String, PathBuf)>,
+	pub output: Option<PathBuf>,
+	pub format: OutputFormat,
+	pub legend: Option<PathBuf>,
+	pub decomposition: Option<Decomposition>,
+	pub connectivity: Option<Connectivity>,
+	pub background: Vec<Color>,
+	pub alpha_threshold: Option<u8>,
+	pub keep_alpha: bool,
+	pub cell_size: Option<CellSize>,
+	pub snap_tolerance: Option<f64>,
+	pub palette: Option<PaletteSource>,
+	pub color_space: Option<ColorSpace>,
+	pub scale: Option<f64>,
+	pub origin: Option<WorldPoint>,
+	pub flip_y: bool,
+	pub anchor: Option<PixelAnchor>,
+	pub tiled_complex: TiledComplex,
+	pub svg: SvgOptions,
+	pub verbosity: Verbosity,
+}
+
+#[derive(Debug)]
+pub enum Command
+{
+	Run(Box<Args>),
+	Help,
+	Version,
+}
+
+/// Invalid command-line arguments, the program prints the usage and exits with `EXIT_USAGE`.
+#[derive(Debug, PartialEq)]
+pub struct UsageError(pub String);
+
+impl UsageError
+{
+	pub fn exit_code(&self) -> u8 {
+		EXIT_USAGE
+	}
+}
+
+impl fmt::Display for UsageError
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+impl From<String> for UsageError
+{
+	fn from(message: String) -> Self {
+		UsageError(message)
+	}
+}
+
+fn parse_point(value: &str) -> Option<WorldPoint> {
+	let (x, y) = value.split_once(',')?;
+	Some(WorldPoint::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+impl Args
+{
+	/// Parses the program arguments without the program name.
+	pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+		Self::parse_args(args).map_err(UsageError)
+	}
+
+	fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+		let mut inputs = Vec::new();
+		let mut layers = Vec::new();
+		let mut output = None;
+		let mut format = OutputFormat::Ron;
+		let mut legend = None;
+		let mut decomposition = None;
+		let mut connectivity = None;
+		let mut background = Vec::new();
+		let mut alpha_threshold = None;
+		let mut keep_alpha = false;
+		let mut cell_size = None;
+		let mut snap_tolerance = None;
+		let mut palette = None;
+		let mut color_space = None;
+		let mut scale = None;
+		let mut origin = None;
+		let mut flip_y = false;
+		let mut anchor = None;
+		let mut tiled_complex = TiledComplex::default();
+		let mut svg = SvgOptions::default();
+		let mut verbosity = Verbosity::Normal;
+		let mut only_inputs = false;
+
+		while let Some(arg) = args.next() {
+			if only_inputs || !arg.starts_with('-') || arg == STDIN {
+				inputs.push(PathBuf::from(arg));
+				continue;
+			}
+
+			let (name, inline_value) = match arg.find('=') {
+				Some(pos) if arg.starts_with("--") => (arg[..pos].to_string(), Some(arg[pos + 1..].to_string())),
+				_ => (arg.clone(), None),
+			};
+
+			let mut value = |option: &str| -> Result<String, String> {
+				inline_value.clone()
+					.or_else(|| args.next())
+					.ok_or_else(|| format!("option `{}` requires a value", option))
+			};
+
+			match name.as_str() {
+				"--" => only_inputs = true,
+				"-h" | "--help" => return Ok(Command::Help),
+				"-V" | "--version" => return Ok(Command::Version),
+				"-v" | "--verbose" => verbosity = Verbosity::Verbose,
+				"-q" | "--quiet" => verbosity = Verbosity::Quiet,
+				"-o" | "--output" => output = Some(PathBuf::from(value(&name)?)),
+				"-L" | "--layer" => {
+					let layer = value(&name)?;
+					let (layer_name, path) = layer.split_once('=')
+						.filter(|(layer_name, path)| !layer_name.is_empty() && !path.is_empty())
+						.ok_or_else(|| format!("invalid layer `{}`, expected `NAME=IMAGE`", layer))?;
+					if path == STDIN {
+						return Err(format!("layer `{}` can't be read from stdin", layer_name));
+					}
+					layers.push((layer_name.to_string(), PathBuf::from(path)));
+				},
+				"-l" | "--legend" => legend = Some(PathBuf::from(value(&name)?)),
+				"-d" | "--decomposition" => {
+					let name = value(&name)?;
+					decomposition = Some(Decomposition::from_name(&name)
+						.ok_or_else(|| format!("unknown decomposition `{}`", name))?);
+				},
+				"-c" | "--connectivity" => {
+					let name = value(&name)?;
+					connectivity = Some(Connectivity::from_name(&name)
+						.ok_or_else(|| format!("unknown connectivity `{}`, expected 4 or 8", name))?);
+				},
+				"-b" | "--background" => {
+					let hex = value(&name)?;
+					background.push(Color::from_hex(&hex)
+						.ok_or_else(|| format!("invalid color `{}`, expected `#RRGGBB`", hex))?);
+				},
+				"--alpha-threshold" => {
+					let alpha = value(&name)?;
+					alpha_threshold = Some(alpha.parse()
+						.map_err(|_| format!("invalid alpha `{}`, expected 0 to 255", alpha))?);
+				},
+				"--keep-alpha" => keep_alpha = true,
+				"--cell-size" => {
+					let size = value(&name)?;
+					cell_size = Some(CellSize::from_name(&size)
+						.ok_or_else(|| format!("invalid cell size `{}`, expected a size in pixels or `auto`", size))?);
+				},
+				"-s" | "--snap" => {
+					let tolerance = value(&name)?;
+					snap_tolerance = Some(tolerance.parse::<f64>().ok().filter(|t| *t >= 0.0)
+						.ok_or_else(|| format!("invalid tolerance `{}`", tolerance))?);
+				},
+				"--palette" => {
+					let name = value(&name)?;
+					palette = Some(PaletteSource::from_name(&name)
+						.ok_or_else(|| format!("unknown palette `{}`, expected `legend` or a colors count", name))?);
+				},
+				"--color-space" => {
+					let name = value(&name)?;
+					color_space = Some(ColorSpace::from_name(&name)
+						.ok_or_else(|| format!("unknown color space `{}`", name))?);
+				},
+				"--scale" => {
+					let units = value(&name)?;
+					scale = Some(units.parse::<f64>().ok().filter(|s| *s > 0.0)
+						.ok_or_else(|| format!("invalid scale `{}`", units))?);
+				},
+				"--origin" => {
+					let position = value(&name)?;
+					origin = Some(parse_point(&position)
+						.ok_or_else(|| format!("invalid origin `{}`, expected `X,Y`", position))?);
+				},
+				"--flip-y" => flip_y = true,
+				"--anchor" => {
+					let name = value(&name)?;
+					anchor = Some(PixelAnchor::from_name(&name)
+						.ok_or_else(|| format!("unknown anchor `{}`", name))?);
+				},
+				"--tiled-complex" => {
+					let name = value(&name)?;
+					tiled_complex = TiledComplex::from_name(&name)
+						.ok_or_else(|| format!("unknown complex shapes export `{}`, expected `polygon` or `rects`", name))?;
+				},
+				"--svg-overlays" => {
+					let list = value(&name)?;
+					let overlays = SvgOptions::from_overlays(&list)
+						.ok_or_else(|| format!("invalid overlays `{}`, expected `outlines`, `holes`, `dependencies` or `all`", list))?;
+					svg = SvgOptions { scale: svg.scale, ..overlays };
+				},
+				"--svg-scale" => {
+					let size = value(&name)?;
+					svg.scale = size.parse().ok().filter(|s| *s > 0)
+						.ok_or_else(|| format!("invalid SVG scale `{}`", size))?;
+				},
+				"-f" | "--format" => {
+					let name = value(&name)?;
+					format = OutputFormat::from_name(&name)
+						.ok_or_else(|| format!("unknown output format `{}`", name))?;
+				},
+				_ => return Err(format!("unknown option `{}`", arg)),
+			}
+		}
+
+		if !layers.is_empty() && !inputs.is_empty() {
+			return Err("input images can't be given together with layers".to_string());
+		}
+
+		if inputs.is_empty() && layers.is_empty() {
+			return Err("no input images given".to_string());
+		}
+
+		if inputs.len() > 1 && inputs.iter().any(|input| input == Path::new(STDIN)) {
+			return Err("an image read from stdin can't be given together with other inputs".to_string());
+		}
+
+		if inputs.len() > 1 && output.as_deref() == Some(Path::new("-")) {
+			return Err("only a single input can be written to stdout".to_string());
+		}
+
+		let args = Args {
+			inputs,
+			layers,
+			output,
+			format,
+			legend,
+			decomposition,
+			connectivity,
+			background,
+			alpha_threshold,
+			keep_alpha,
+			cell_size,
+			snap_tolerance,
+			palette,
+			color_space,
+			scale,
+			origin,
+			flip_y,
+			anchor,
+			tiled_complex,
+			svg,
+			verbosity,
+		};
+
+		// inputs of the same file name in different directories, or differing only by extension
+		let mut inputs_by_output: HashMap<PathBuf, &PathBuf> = HashMap::new();
+		for input in &args.inputs {
+			if let Output::File(path) = args.get_output(input) {
+				if let Some(other) = inputs_by_output.insert(path.clone(), input) {
+					return Err(format!("`{}` and `{}` would both be written to `{}`", other.display(), input.display(), path.display()));
+				}
+			}
+		}
+
+		Ok(Command::Run(Box::new(args)))
+	}
+
+	pub fn get_output(&self, input: &Path) -> Output {
+		let file_name = Path::new(input.file_name().unwrap_or_default()).with_extension(self.format.extension());
+
+		match &self.output {
+			Some(output) if output == Path::new("-") => Output::Stdout,
+			Some(output) if self.inputs.len() <= 1 => Output::File(output.clone()),
+			Some(output_dir) => Output::File(output_dir.join(file_name)),
+			None if input == Path::new(STDIN) => Output::Stdout,
+			None => Output::File(input.with_extension(self.format.extension())),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,182 @@
+use super::*;
+
+use std::path::{Path, PathBuf};
+
+use layouter::{CellSize, Color, Connectivity, Decomposition, PixelAnchor, SvgOptions, TiledComplex, WorldPoint};
+
+fn parse(args: &[&str]) -> Result<Command, UsageError> {
+	Args::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+fn parse_args(args: &[&str]) -> Box<Args> {
+	match parse(args) {
+		Ok(Command::Run(args)) => args,
+		other => panic!("{:?}: {:?}", args, other),
+	}
+}
+
+fn parse_error(args: &[&str]) -> String {
+	let error = parse(args).unwrap_err();
+	assert_eq!(error.exit_code(), EXIT_USAGE, "{:?}", args);
+	error.0
+}
+
+#[test]
+fn test_defaults() {
+	let args = parse_args(&["level.png"]);
+
+	assert_eq!(args.inputs, vec![PathBuf::from("level.png")]);
+	assert!(args.layers.is_empty());
+	assert_eq!(args.output, None);
+	assert_eq!(args.format, OutputFormat::Ron);
+	assert_eq!(args.legend, None);
+	assert_eq!(args.cell_size, None);
+	assert_eq!(args.scale, None);
+	assert!(!args.flip_y);
+	assert_eq!(args.tiled_complex, TiledComplex::Polygon);
+	assert_eq!(args.svg, SvgOptions::default());
+	assert_eq!(args.verbosity, Verbosity::Normal);
+	assert_eq!(args.get_output(Path::new("level.png")), Output::File(PathBuf::from("level.ron")));
+}
+
+#[test]
+fn test_help_and_version() {
+	assert!(matches!(parse(&["-h"]), Ok(Command::Help)));
+	assert!(matches!(parse(&["level.png", "--help"]), Ok(Command::Help)));
+	assert!(matches!(parse(&["-V"]), Ok(Command::Version)));
+	assert!(matches!(parse(&["--version", "--unknown"]), Ok(Command::Version)));
+}
+
+#[test]
+fn test_usage_errors() {
+	assert_eq!(parse_error(&[]), "no input images given");
+	assert_eq!(parse_error(&["--unknown", "level.png"]), "unknown option `--unknown`");
+	assert_eq!(parse_error(&["level.png", "-x"]), "unknown option `-x`");
+	assert_eq!(parse_error(&["level.png", "--format"]), "option `--format` requires a value");
+	assert_eq!(parse_error(&["level.png", "-f", "png"]), "unknown output format `png`");
+	assert_eq!(parse_error(&["level.png", "--connectivity=6"]), "unknown connectivity `6`, expected 4 or 8");
+	assert_eq!(parse_error(&["level.png", "-b", "white"]), "invalid color `white`, expected `#RRGGBB`");
+	assert_eq!(parse_error(&["level.png", "--alpha-threshold", "256"]), "invalid alpha `256`, expected 0 to 255");
+	assert_eq!(parse_error(&["level.png", "-s", "-1"]), "invalid tolerance `-1`");
+}
+
+#[test]
+fn test_output() {
+	let args = parse_args(&["-o", "-", "level.png"]);
+	assert_eq!(args.get_output(Path::new("level.png")), Output::Stdout);
+
+	assert_eq!(parse_error(&["-o", "-", "a.png", "b.png"]), "only a single input can be written to stdout");
+
+	let args = parse_args(&["--output=out.json", "-f", "JSON", "level.png"]);
+	assert_eq!(args.get_output(Path::new("level.png")), Output::File(PathBuf::from("out.json")));
+
+	// several inputs are written into the output directory
+	let args = parse_args(&["-o", "out", "--format", "tmx", "a/first.png", "b/second.png"]);
+	assert_eq!(args.get_output(Path::new("a/first.png")), Output::File(PathBuf::from("out/first.tmx")));
+	assert_eq!(args.get_output(Path::new("b/second.png")), Output::File(PathBuf::from("out/second.tmx")));
+
+	assert_eq!(parse_error(&["-o", "out", "a/level.png", "b/level.png"]), "`a/level.png` and `b/level.png` would both be written to `out/level.ron`");
+	assert_eq!(parse_error(&["level.png", "level.gif"]), "`level.png` and `level.gif` would both be written to `level.ron`");
+	assert_eq!(parse_error(&["level.png", "level.png"]), "`level.png` and `level.png` would both be written to `level.ron`");
+}
+
+#[test]
+fn test_stdin_input() {
+	let args = parse_args(&["-f", "json", "-"]);
+	assert_eq!(args.inputs, vec![PathBuf::from(STDIN)]);
+	assert_eq!(args.get_output(Path::new(STDIN)), Output::Stdout);
+
+	let args = parse_args(&["-", "-o", "level.ron"]);
+	assert_eq!(args.get_output(Path::new(STDIN)), Output::File(PathBuf::from("level.ron")));
+
+	assert_eq!(parse_error(&["-", "level.png"]), "an image read from stdin can't be given together with other inputs");
+	assert_eq!(parse_error(&["-L", "walls=-"]), "layer `walls` can't be read from stdin");
+}
+
+#[test]
+fn test_separator() {
+	let args = parse_args(&["-q", "--", "-v", "--format"]);
+
+	assert_eq!(args.inputs, vec![PathBuf::from("-v"), PathBuf::from("--format")]);
+	assert_eq!(args.format, OutputFormat::Ron);
+	assert_eq!(args.verbosity, Verbosity::Quiet);
+}
+
+#[test]
+fn test_legend_options() {
+	let args = parse_args(&[
+		"-l", "level.legend.ron", "-d", "minimum-rectangles", "-c", "8",
+		"-b", "#000000", "--background=#FF00FF", "--alpha-threshold", "128", "--keep-alpha", "level.png",
+	]);
+
+	assert_eq!(args.legend, Some(PathBuf::from("level.legend.ron")));
+	assert_eq!(args.decomposition, Some(Decomposition::MinimumRectangles));
+	assert_eq!(args.connectivity, Some(Connectivity::Eight));
+	assert_eq!(args.background, vec![Color::BLACK, Color::new(255, 0, 255)]);
+	assert_eq!(args.alpha_threshold, Some(128));
+	assert!(args.keep_alpha);
+}
+
+#[test]
+fn test_cell_size() {
+	assert_eq!(parse_args(&["--cell-size", "16", "level.png"]).cell_size, Some(CellSize::Fixed(16)));
+	assert_eq!(parse_args(&["--cell-size=auto", "level.png"]).cell_size, Some(CellSize::Auto));
+
+	assert_eq!(parse_error(&["--cell-size", "big", "level.png"]), "invalid cell size `big`, expected a size in pixels or `auto`");
+}
+
+#[test]
+fn test_world_transform() {
+	let args = parse_args(&["--scale", "0.5", "--origin", "-4, 2.5", "--flip-y", "--anchor", "center", "level.png"]);
+
+	assert_eq!(args.scale, Some(0.5));
+	assert_eq!(args.origin, Some(WorldPoint::new(-4.0, 2.5)));
+	assert!(args.flip_y);
+	assert_eq!(args.anchor, Some(PixelAnchor::Center));
+
+	assert_eq!(parse_error(&["--scale", "0", "level.png"]), "invalid scale `0`");
+	assert_eq!(parse_error(&["--origin", "4", "level.png"]), "invalid origin `4`, expected `X,Y`");
+	assert_eq!(parse_error(&["--anchor", "top", "level.png"]), "unknown anchor `top`");
+}
+
+#[test]
+fn test_layers() {
+	let args = parse_args(&["-L", "walls=walls.png", "--layer=decor=decor.png", "-f", "ldtk"]);
+
+	assert!(args.inputs.is_empty());
+	assert_eq!(args.layers, vec![
+		("walls".to_string(), PathBuf::from("walls.png")),
+		("decor".to_string(), PathBuf::from("decor.png")),
+	]);
+
+	assert_eq!(parse_error(&["-L", "walls.png"]), "invalid layer `walls.png`, expected `NAME=IMAGE`");
+	assert_eq!(parse_error(&["-L", "=walls.png"]), "invalid layer `=walls.png`, expected `NAME=IMAGE`");
+	assert_eq!(parse_error(&["-L", "walls=walls.png", "level.png"]), "input images can't be given together with layers");
+}
+
+#[test]
+fn test_tiled_options() {
+	let args = parse_args(&["-f", "tmj", "--tiled-complex", "rects", "level.png"]);
+
+	assert_eq!(args.format, OutputFormat::Tmj);
+	assert_eq!(args.tiled_complex, TiledComplex::Rects);
+	assert_eq!(parse_args(&["--format=tmx", "level.png"]).format, OutputFormat::Tmx);
+
+	assert_eq!(
+		parse_error(&["--tiled-complex", "tiles", "level.png"]),
+		"unknown complex shapes export `tiles`, expected `polygon` or `rects`",
+	);
+}
+
+#[test]
+fn test_svg_options() {
+	// the scale is kept whatever order the options are given in
+	let args = parse_args(&["-f", "svg", "--svg-scale", "8", "--svg-overlays", "outlines,holes", "level.png"]);
+
+	assert_eq!(args.format, OutputFormat::Svg);
+	assert_eq!(args.svg, SvgOptions { scale: 8, outlines: true, holes: true, dependencies: false });
+	assert_eq!(parse_args(&["--svg-overlays=all", "--svg-scale=4", "level.png"]).svg, SvgOptions { scale: 4, outlines: true, holes: true, dependencies: true });
+
+	assert_eq!(parse_error(&["--svg-scale", "0", "level.png"]), "invalid SVG scale `0`");
+	assert!(parse_error(&["--svg-overlays", "edges", "level.png"]).starts_with("invalid overlays `edges`"));
+}
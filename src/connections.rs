@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::labeling::Connectivity;
+use crate::legend::Legend;
+use crate::types::{
+	Color,
+	Point,
+	Shape,
+	ShapesLayout,
+	Warning,
+};
+
+/// Shape or entity of a layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ShapeId
+{
+	/// Index of the shape among `shapes` of the color.
+	Shape(Color, usize),
+	/// Index of the entity in `entities`.
+	Entity(usize),
+}
+
+/// Line of a connection layer joining two shapes, its `source` is the one the line reaches first in scanline order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Connection
+{
+	pub source: ShapeId,
+	pub target: ShapeId,
+}
+
+impl ShapesLayout
+{
+	pub fn get_shape(&self, color: &Color, index: usize) -> Option<&Shape> {
+		self.shapes.get(color)?.get(index)
+	}
+
+	/// Shapes and entities connected to `id` in the connections order.
+	pub fn get_connected(&self, id: ShapeId) -> Vec<ShapeId> {
+		self.connections.iter()
+			.filter_map(|connection| {
+				if connection.source == id {
+					Some(connection.target)
+				} else if connection.target == id {
+					Some(connection.source)
+				} else {
+					None
+				}
+			})
+			.collect()
+	}
+
+	/// Removes shapes of the legend connection layers and returns the pairs of shapes joined by them.
+	/// Lines joining other than two shapes are reported as warnings.
+	pub(crate) fn extract_connections(&mut self, legend: &Legend) -> Vec<((Color, usize), (Color, usize))> {
+		let lines: BTreeMap<Color, Vec<Shape>> = legend.layers.values()
+			.filter(|layer| layer.connection)
+			.filter_map(|layer| self.shapes.remove(&layer.color).map(|shapes| (layer.color, shapes)))
+			.collect();
+
+		if lines.is_empty() {
+			return Vec::new();
+		}
+
+		let mut pairs = Vec::new();
+
+		for (color, shapes) in &lines {
+			let neighbours: &[(i8, i8)] = match legend.get_connectivity(color) {
+				Connectivity::Four => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+				Connectivity::Eight => &[(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)],
+			};
+
+			for line in shapes {
+				let mut points: Vec<Point> = line.get_covered_points().into_iter().collect();
+				points.sort();
+
+				let nearby = self.get_shapes_near(&line.get_bbox());
+
+				// shapes touched by the line in the order they are reached
+				let mut touched: Vec<(Color, usize)> = Vec::new();
+				for point in &points {
+					for (dx, dy) in neighbours {
+						let shape = point.get_neighbour(*dx, *dy).and_then(|neighbour| {
+							nearby.iter().find(|(color, index)| self.shapes[color][*index].contains(&neighbour))
+						});
+
+						if let Some(shape) = shape {
+							if !touched.contains(shape) {
+								touched.push(*shape);
+							}
+						}
+					}
+				}
+
+				if touched.len() == 2 {
+					pairs.push((touched[0], touched[1]));
+				} else {
+					self.warnings.push(Warning::DanglingConnection { point: points[0], shapes_count: touched.len() });
+				}
+			}
+		}
+
+		pairs
+	}
+}
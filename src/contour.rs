@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+
+use serde::{Serialize, Deserialize};
+
+use crate::labeling::{self, Components, Connectivity, NO_LABEL};
+use crate::types::{
+	BBox,
+	Point,
+};
+use crate::utils;
+
+/// Rectilinear boundary of a connected region in pixel-corner coordinates:
+/// the pixel `(x, y)` spans corners `(x, y)` to `(x + 1, y + 1)`.
+/// Loops are closed implicitly and keep the region on the right of every edge (y pointing down),
+/// so the outer boundary goes clockwise and holes go counter-clockwise.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outline
+{
+	/// Outer boundary starting at the top-left corner of the region.
+	pub boundary: Vec<Point>,
+	/// Boundaries of the enclosed empty regions in scanline order.
+	pub holes: Vec<Vec<Point>>,
+}
+
+impl Outline
+{
+	pub fn from_bbox(bbox: &BBox) -> Self {
+		let (min, max) = (bbox.min, Point::new(bbox.max.x + 1, bbox.max.y + 1));
+
+		Self {
+			boundary: vec![min, Point::new(max.x, min.y), max, Point::new(min.x, max.y)],
+			holes: Vec::new(),
+		}
+	}
+
+	pub fn get_holes(&self) -> Vec<Hole> {
+		self.holes.iter().cloned().map(Hole::new).collect()
+	}
+}
+
+/// Empty region enclosed by a shape.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hole
+{
+	/// Bounding box of the hole pixels.
+	pub bbox: BBox,
+	/// Boundary of the hole in pixel-corner coordinates, counter-clockwise.
+	pub outline: Vec<Point>,
+}
+
+impl Hole
+{
+	pub fn new(outline: Vec<Point>) -> Self {
+		let min_x = outline.iter().map(|p| p.x).min().unwrap_or(0);
+		let min_y = outline.iter().map(|p| p.y).min().unwrap_or(0);
+		let max_x = outline.iter().map(|p| p.x).max().unwrap_or(1);
+		let max_y = outline.iter().map(|p| p.y).max().unwrap_or(1);
+
+		Self {
+			bbox: BBox::new_xy(min_x, min_y, max_x - 1, max_y - 1),
+			outline,
+		}
+	}
+
+	/// Count of pixels inside the hole outline, islands of other regions included.
+	pub fn get_points_count(&self) -> usize {
+		let doubled: isize = self.outline.iter().zip(self.outline.iter().cycle().skip(1))
+			.map(|(a, b)| b.x as isize * a.y as isize - a.x as isize * b.y as isize)
+			.sum();
+
+		(doubled / 2) as usize
+	}
+
+	pub fn contains(&self, point: &Point) -> bool {
+		if !self.bbox.contains(point) {
+			return false;
+		}
+
+		// crossings of the vertical edges on the left of the pixel center
+		let crossings = self.outline.iter().zip(self.outline.iter().cycle().skip(1))
+			.filter(|(a, b)| a.x == b.x && a.x <= point.x && a.y.min(b.y) <= point.y && point.y < a.y.max(b.y))
+			.count();
+
+		crossings % 2 == 1
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction
+{
+	East,
+	South,
+	West,
+	North,
+}
+
+impl Direction
+{
+	const ALL: [Direction; 4] = [Direction::East, Direction::South, Direction::West, Direction::North];
+
+	fn turn_right(self) -> Self {
+		Self::ALL[(self as usize + 1) % 4]
+	}
+
+	fn turn_left(self) -> Self {
+		Self::ALL[(self as usize + 3) % 4]
+	}
+
+	fn step(self, x: usize, y: usize) -> (usize, usize) {
+		match self {
+			Direction::East => (x + 1, y),
+			Direction::South => (x, y + 1),
+			Direction::West => (x - 1, y),
+			Direction::North => (x, y - 1),
+		}
+	}
+
+	/// Cells on the right and on the left of the edge going from corner `(x, y)` in this direction.
+	fn get_sides(self, x: usize, y: usize) -> ((isize, isize), (isize, isize)) {
+		let (x, y) = (x as isize, y as isize);
+
+		match self {
+			Direction::East => ((x, y), (x, y - 1)),
+			Direction::South => ((x - 1, y), (x, y)),
+			Direction::West => ((x - 1, y - 1), (x - 1, y)),
+			Direction::North => ((x, y - 1), (x - 1, y - 1)),
+		}
+	}
+}
+
+struct Tracer<'a>
+{
+	components: &'a Components,
+	connectivity: Connectivity,
+	/// Directions of the traced edges by their start corner.
+	used: Vec<u8>,
+}
+
+impl<'a> Tracer<'a>
+{
+	fn new(components: &'a Components, connectivity: Connectivity) -> Self {
+		Self {
+			components,
+			connectivity,
+			used: vec![0; (components.width + 1) * (components.height + 1)],
+		}
+	}
+
+	fn get_label(&self, (x, y): (isize, isize)) -> u32 {
+		if x < 0 || y < 0 || x as usize >= self.components.width || y as usize >= self.components.height {
+			return NO_LABEL;
+		}
+
+		self.components.get_label(x as usize, y as usize)
+	}
+
+	/// The region `label` is on the right of the edge and not on the left.
+	fn is_edge(&self, x: usize, y: usize, direction: Direction, label: u32) -> bool {
+		let (right, left) = direction.get_sides(x, y);
+		self.get_label(right) == label && self.get_label(left) != label
+	}
+
+	fn is_used(&self, x: usize, y: usize, direction: Direction) -> bool {
+		self.used[y * (self.components.width + 1) + x] & (1 << direction as u8) != 0
+	}
+
+	fn set_used(&mut self, x: usize, y: usize, direction: Direction) {
+		self.used[y * (self.components.width + 1) + x] |= 1 << direction as u8;
+	}
+
+	/// Corners of the loop starting with the edge from `(x, y)`, the loop starts at a turn.
+	fn trace(&mut self, x: usize, y: usize, direction: Direction, label: u32, origin: &Point) -> Vec<Point> {
+		let start = (x, y, direction);
+		let mut vertices = Vec::new();
+
+		let (mut x, mut y, mut direction) = start;
+		loop {
+			self.set_used(x, y, direction);
+			let (next_x, next_y) = direction.step(x, y);
+
+			// where pixels touch by a corner only, turn right to stay around the same pixel
+			// or left to go around the diagonal neighbour if it's connected
+			let turns = match self.connectivity {
+				Connectivity::Four => [direction.turn_right(), direction, direction.turn_left()],
+				Connectivity::Eight => [direction.turn_left(), direction, direction.turn_right()],
+			};
+
+			let next_direction = turns.iter()
+				.cloned()
+				.find(|d| self.is_edge(next_x, next_y, *d, label))
+				.unwrap();
+
+			if next_direction != direction {
+				vertices.push(Point::new(origin.x + next_x, origin.y + next_y));
+			}
+
+			x = next_x;
+			y = next_y;
+			direction = next_direction;
+
+			if (x, y, direction) == start {
+				break;
+			}
+		}
+
+		// the start corner closes the loop
+		vertices.rotate_right(1);
+		vertices
+	}
+}
+
+/// Outlines of the connected regions of `points`, in scanline order of the regions.
+pub fn trace_outlines(points: &HashSet<Point>, connectivity: Connectivity) -> Vec<Outline> {
+	if points.is_empty() {
+		return Vec::new();
+	}
+
+	let bbox = utils::calc_bbox_by_points(points);
+
+	let components = labeling::label_components(bbox.get_width(), bbox.get_height(), |x, y| {
+		if points.contains(&Point::new(bbox.min.x + x, bbox.min.y + y)) { Some(()) } else { None }
+	}, |_| connectivity);
+
+	trace_components(&components, &bbox.min, connectivity)
+}
+
+/// Outlines of every component in labels order, `origin` is the position of the components grid.
+/// Components must be labeled with the same `connectivity`.
+pub fn trace_components(components: &Components, origin: &Point, connectivity: Connectivity) -> Vec<Outline> {
+	let mut outlines = vec![Outline::default(); components.get_count()];
+	let mut tracer = Tracer::new(components, connectivity);
+
+	for y in 0 ..= components.height {
+		for x in 0 ..= components.width {
+			for direction in &Direction::ALL {
+				let label = tracer.get_label(direction.get_sides(x, y).0);
+				if label == NO_LABEL || tracer.is_used(x, y, *direction) || !tracer.is_edge(x, y, *direction, label) {
+					continue;
+				}
+
+				let vertices = tracer.trace(x, y, *direction, label, origin);
+
+				// the top-left corner of a region is met before any of its holes
+				let outline = &mut outlines[label as usize];
+				if outline.boundary.is_empty() {
+					outline.boundary = vertices;
+				} else {
+					outline.holes.push(vertices);
+				}
+			}
+		}
+	}
+
+	outlines
+}
@@ -0,0 +1,634 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use serde::{Serialize, Deserialize};
+
+use crate::labeling::Connectivity;
+use crate::types::{
+	BBox,
+	Point,
+	Shape,
+};
+use crate::utils;
+
+/// Boxes and single points a complex shape is split into.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SplittedComplexGeometry
+{
+	bboxes: BTreeSet<BBox>,
+	points: BTreeSet<Point>,
+}
+
+impl SplittedComplexGeometry
+{
+	pub fn new(bboxes: BTreeSet<BBox>, points: BTreeSet<Point>) -> Self {
+		Self { bboxes, points }
+	}
+
+	pub fn merge(&mut self, other: SplittedComplexGeometry) {
+		self.points.extend(other.points);
+		self.bboxes.extend(other.bboxes);
+	}
+
+	/// Adds a box, one point boxes are stored as points.
+	pub fn add_bbox(&mut self, bbox: BBox) {
+		if bbox.is_point() {
+			self.points.insert(bbox.min);
+		} else {
+			self.bboxes.insert(bbox);
+		}
+	}
+
+	pub fn add_point(&mut self, point: Point) {
+		self.points.insert(point);
+	}
+
+	pub fn get_bboxes(&self) -> &BTreeSet<BBox> {
+		&self.bboxes
+	}
+
+	pub fn get_points(&self) -> &BTreeSet<Point> {
+		&self.points
+	}
+
+	pub fn into_parts(self) -> (BTreeSet<BBox>, BTreeSet<Point>) {
+		(self.bboxes, self.points)
+	}
+}
+
+/// Splits points of a complex shape into boxes; every box becomes a collider, so fewer is better.
+pub trait DecompositionStrategy
+{
+	fn split(&self, points: &HashSet<Point>, outer_bbox: &BBox) -> SplittedComplexGeometry;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Decomposition
+{
+	/// Greedy: the tallest column first, then the rest recursively.
+	#[default]
+	TallestColumn,
+	/// Greedy: horizontal runs merged with equal runs of the next rows.
+	RowMerge,
+	/// Optimal partition into the minimum number of rectangles.
+	MinimumRectangles,
+}
+
+impl Decomposition
+{
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"tallest-column" | "TallestColumn" => Some(Decomposition::TallestColumn),
+			"row-merge" | "RowMerge" => Some(Decomposition::RowMerge),
+			"minimum-rectangles" | "MinimumRectangles" => Some(Decomposition::MinimumRectangles),
+			_ => None,
+		}
+	}
+
+	pub fn get_strategy(&self) -> &'static dyn DecompositionStrategy {
+		match self {
+			Decomposition::TallestColumn => &TallestColumn,
+			Decomposition::RowMerge => &RowMerge,
+			Decomposition::MinimumRectangles => &MinimumRectangles,
+		}
+	}
+}
+
+pub struct TallestColumn;
+
+impl DecompositionStrategy for TallestColumn
+{
+	fn split(&self, points: &HashSet<Point>, outer_bbox: &BBox) -> SplittedComplexGeometry {
+		let mut geometry = SplittedComplexGeometry::default();
+		self._split(&mut geometry, points, outer_bbox);
+		geometry
+	}
+}
+
+impl TallestColumn
+{
+	fn _split(&self, geometry: &mut SplittedComplexGeometry, points: &HashSet<Point>, outer_bbox: &BBox) {
+		if points.is_empty() {
+			return;
+		}
+
+		// find the heightiest column of pixels in all shape
+		// then we'll find the largest bbox of all shape with this height
+		let mut min_y = outer_bbox.max.y;
+		let mut max_y = 0usize;
+		{
+			let mut max_height = 0usize;
+
+			for x in outer_bbox.min.x ..= outer_bbox.max.x {
+				let mut column_started = false;
+				let mut min_column_y: Option<usize> = None;
+				let mut max_column_y: Option<usize> = None;
+				let mut column_height = 0usize;
+
+				for y in outer_bbox.min.y ..= outer_bbox.max.y {
+					if points.contains(&Point::new(x, y)) {
+						if !column_started {
+							column_started = true;
+							min_column_y.replace(y);
+							column_height = 1;
+						} else {
+							max_column_y.replace(y);
+							column_height += 1;
+						}
+					} else {
+						if let Some(column_min_y) = min_column_y {
+							if column_height > max_height {
+								max_height = column_height;
+								min_y = column_min_y;
+								max_y = max_column_y.unwrap_or(min_y);
+							}
+						}
+						column_height = 0;
+						column_started = false;
+						min_column_y = None;
+						max_column_y = None;
+					}
+				}
+
+				if column_height > max_height {
+					max_height = column_height;
+					min_y = min_column_y.unwrap();
+					max_y = max_column_y.unwrap_or(min_y);
+				}
+			}
+		}
+
+		// find heightest bboxes
+		let min_y = min_y;
+		let max_y = max_y;
+
+		let mut heightiest_bboxes = Vec::new();
+
+		let mut min_x: Option<usize> = None;
+		let mut max_x: Option<usize> = None;
+
+		for x in outer_bbox.min.x ..= outer_bbox.max.x {
+			let mut match_height: usize = 0;
+			for y in min_y ..= max_y {
+				let p = Point::new(x, y);
+				if points.contains(&p) {
+					match_height += 1;
+				} else {
+					break;
+				}
+			}
+
+			if match_height == (max_y - min_y + 1) {
+				if min_x.is_none() {
+					min_x.replace(x);
+				} else {
+					max_x.replace(x);
+				}
+			} else {
+				if min_x.is_some() {
+					if max_x.is_none() {
+						max_x.replace(min_x.unwrap());
+					}
+
+					heightiest_bboxes.push(BBox::new_xy(min_x.take().unwrap(), min_y, max_x.take().unwrap(), max_y));
+				} else {
+					min_x = None;
+					max_x = None;
+				}
+			}
+		}
+
+		if min_x.is_some() {
+			if max_x.is_none() {
+				max_x.replace(min_x.unwrap());
+			}
+
+			heightiest_bboxes.push(BBox::new_xy(min_x.take().unwrap(), min_y, max_x.take().unwrap(), max_y));
+		}
+
+		for bbox in &heightiest_bboxes {
+			if bbox.is_point() {
+				geometry.points.insert(bbox.min);
+			} else {
+				geometry.bboxes.insert(*bbox);
+			}
+		}
+
+		// process rest shapes that did not belong to heightiest bboxes
+		{
+			let rest_shapes = self._split_points_by_bboxes(points.clone(), heightiest_bboxes);
+			for shape in &rest_shapes {
+				match shape {
+					Shape::Pixel(point) => {geometry.points.insert(*point);},
+					Shape::Box(bbox) => {geometry.bboxes.insert(*bbox);},
+					Shape::Complex(geom) => {geometry.merge(geom.copy_inner_geometry());},
+				}
+			}
+		}
+	}
+
+	fn _split_points_by_bboxes(&self, mut points: HashSet<Point>, heightiest_bboxes: Vec<BBox>) -> Vec<Shape> {
+		for bbox in heightiest_bboxes {
+			for x in bbox.min.x ..= bbox.max.x {
+				for y in bbox.min.y ..= bbox.max.y {
+					points.remove(&Point::new(x, y));
+				}
+			}
+		}
+
+		utils::get_shapes_by_points(&points, self, Connectivity::Four)
+	}
+}
+
+/// Points of a shape rasterized into its outer bbox.
+/// Cells are addressed by `(x, y)` relative to the bbox, corners of cells by `(x, y)` in `0 ..= width`, `0 ..= height`.
+struct Grid
+{
+	cells: Vec<bool>,
+	width: usize,
+	height: usize,
+	origin: Point,
+}
+
+impl Grid
+{
+	fn new(points: &HashSet<Point>, outer_bbox: &BBox) -> Self {
+		let (width, height) = (outer_bbox.get_width(), outer_bbox.get_height());
+		let mut cells = vec![false; width * height];
+
+		for p in points {
+			cells[(p.y - outer_bbox.min.y) * width + (p.x - outer_bbox.min.x)] = true;
+		}
+
+		Self { cells, width, height, origin: outer_bbox.min }
+	}
+
+	fn is_inside(&self, x: isize, y: isize) -> bool {
+		x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height && self.cells[y as usize * self.width + x as usize]
+	}
+
+	/// Horizontal segment from corner `(x, y)` to `(x + 1, y)` has the shape on both sides.
+	fn is_interior_h(&self, x: usize, y: usize) -> bool {
+		let (x, y) = (x as isize, y as isize);
+		self.is_inside(x, y - 1) && self.is_inside(x, y)
+	}
+
+	/// Vertical segment from corner `(x, y)` to `(x, y + 1)` has the shape on both sides.
+	fn is_interior_v(&self, x: usize, y: usize) -> bool {
+		let (x, y) = (x as isize, y as isize);
+		self.is_inside(x - 1, y) && self.is_inside(x, y)
+	}
+
+	fn to_bbox(&self, min_x: usize, min_y: usize, max_x: usize, max_y: usize) -> BBox {
+		BBox::new_xy(self.origin.x + min_x, self.origin.y + min_y, self.origin.x + max_x, self.origin.y + max_y)
+	}
+
+	/// Maximal horizontal runs of the row `y` as `(min_x, max_x)`.
+	fn get_runs(&self, y: usize) -> Vec<(usize, usize)> {
+		let mut runs = Vec::new();
+		let mut start = None;
+
+		for x in 0 ..= self.width {
+			let inside = x < self.width && self.cells[y * self.width + x];
+
+			match (inside, start) {
+				(true, None) => start = Some(x),
+				(false, Some(min_x)) => {
+					runs.push((min_x, x - 1));
+					start = None;
+				},
+				_ => {},
+			}
+		}
+
+		runs
+	}
+}
+
+pub struct RowMerge;
+
+impl DecompositionStrategy for RowMerge
+{
+	fn split(&self, points: &HashSet<Point>, outer_bbox: &BBox) -> SplittedComplexGeometry {
+		let mut geometry = SplittedComplexGeometry::default();
+		if points.is_empty() {
+			return geometry;
+		}
+
+		let grid = Grid::new(points, outer_bbox);
+
+		// boxes still growing down, by their horizontal run
+		let mut open: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+
+		for y in 0 ..= grid.height {
+			let runs = if y < grid.height { grid.get_runs(y) } else { Vec::new() };
+
+			let mut next_open = BTreeMap::new();
+			for run in runs {
+				let min_y = open.remove(&run).unwrap_or(y);
+				next_open.insert(run, min_y);
+			}
+
+			for ((min_x, max_x), min_y) in open {
+				geometry.add_bbox(grid.to_bbox(min_x, min_y, max_x, y - 1));
+			}
+
+			open = next_open;
+		}
+
+		geometry
+	}
+}
+
+/// Corner of the shape outline with an inner angle of 270 degrees.
+/// Cutting the shape from it along `dx` or `dy` removes the concavity.
+#[derive(Clone, Copy, Debug)]
+struct ReflexVertex
+{
+	x: usize,
+	y: usize,
+	dx: isize,
+	dy: isize,
+}
+
+/// Cut along the line `fixed` (y of horizontal cuts, x of vertical ones) between corners `from` and `to`.
+#[derive(Clone, Copy, Debug)]
+struct Chord
+{
+	fixed: usize,
+	from: usize,
+	to: usize,
+}
+
+impl Chord
+{
+	fn intersects(horizontal: &Chord, vertical: &Chord) -> bool {
+		horizontal.from <= vertical.fixed && vertical.fixed <= horizontal.to &&
+		vertical.from <= horizontal.fixed && horizontal.fixed <= vertical.to
+	}
+}
+
+#[derive(Default)]
+struct Walls
+{
+	/// Horizontal cut segments by their left corner.
+	horizontal: HashSet<(usize, usize)>,
+	/// Vertical cut segments by their top corner.
+	vertical: HashSet<(usize, usize)>,
+}
+
+impl Walls
+{
+	fn add_horizontal(&mut self, chord: &Chord) {
+		for x in chord.from .. chord.to {
+			self.horizontal.insert((x, chord.fixed));
+		}
+	}
+
+	fn add_vertical(&mut self, chord: &Chord) {
+		for y in chord.from .. chord.to {
+			self.vertical.insert((chord.fixed, y));
+		}
+	}
+
+	fn touches_horizontal(&self, x: usize, y: usize) -> bool {
+		(x > 0 && self.horizontal.contains(&(x - 1, y))) || self.horizontal.contains(&(x, y))
+	}
+
+	fn touches(&self, x: usize, y: usize) -> bool {
+		self.touches_horizontal(x, y) ||
+		(y > 0 && self.vertical.contains(&(x, y - 1))) || self.vertical.contains(&(x, y))
+	}
+}
+
+/// Partition of a rectilinear polygon (holes included) into the minimum number of rectangles:
+/// the maximum set of non-crossing chords between reflex vertices is found by bipartite matching,
+/// then every reflex vertex left is resolved by one more cut.
+pub struct MinimumRectangles;
+
+impl DecompositionStrategy for MinimumRectangles
+{
+	fn split(&self, points: &HashSet<Point>, outer_bbox: &BBox) -> SplittedComplexGeometry {
+		if points.is_empty() {
+			return SplittedComplexGeometry::default();
+		}
+
+		let grid = Grid::new(points, outer_bbox);
+		let vertices = Self::_get_reflex_vertices(&grid);
+		let (horizontal, vertical) = Self::_get_chords(&grid, &vertices);
+		let (horizontal, vertical) = Self::_get_max_independent_chords(&horizontal, &vertical);
+
+		let mut walls = Walls::default();
+		horizontal.iter().for_each(|chord| walls.add_horizontal(chord));
+		vertical.iter().for_each(|chord| walls.add_vertical(chord));
+
+		for vertex in &vertices {
+			if walls.touches(vertex.x, vertex.y) {
+				continue;
+			}
+
+			// cut down or up until the outline or another cut
+			let mut y = vertex.y;
+			loop {
+				if vertex.dy < 0 && y == 0 {
+					break;
+				}
+
+				let segment_y = if vertex.dy > 0 { y } else { y - 1 };
+				if !grid.is_interior_v(vertex.x, segment_y) || walls.vertical.contains(&(vertex.x, segment_y)) {
+					break;
+				}
+
+				walls.vertical.insert((vertex.x, segment_y));
+				y = (y as isize + vertex.dy) as usize;
+
+				if walls.touches_horizontal(vertex.x, y) {
+					break;
+				}
+			}
+		}
+
+		Self::_split_by_walls(&grid, &walls)
+	}
+}
+
+impl MinimumRectangles
+{
+	fn _get_reflex_vertices(grid: &Grid) -> Vec<ReflexVertex> {
+		let mut vertices = Vec::new();
+
+		for y in 0 ..= grid.height {
+			for x in 0 ..= grid.width {
+				let (cx, cy) = (x as isize, y as isize);
+				let top_left = grid.is_inside(cx - 1, cy - 1);
+				let top_right = grid.is_inside(cx, cy - 1);
+				let bottom_left = grid.is_inside(cx - 1, cy);
+				let bottom_right = grid.is_inside(cx, cy);
+
+				let (dx, dy) = match (top_left, top_right, bottom_left, bottom_right) {
+					(false, true, true, true) => (1, 1),
+					(true, false, true, true) => (-1, 1),
+					(true, true, false, true) => (1, -1),
+					(true, true, true, false) => (-1, -1),
+					_ => continue,
+				};
+
+				vertices.push(ReflexVertex { x, y, dx, dy });
+			}
+		}
+
+		vertices
+	}
+
+	/// Chords connecting two reflex vertices by a straight cut through the shape.
+	fn _get_chords(grid: &Grid, vertices: &[ReflexVertex]) -> (Vec<Chord>, Vec<Chord>) {
+		let directions: HashMap<(usize, usize), (isize, isize)> = vertices.iter().map(|v| ((v.x, v.y), (v.dx, v.dy))).collect();
+
+		let mut horizontal = Vec::new();
+		let mut vertical = Vec::new();
+
+		for vertex in vertices {
+			// every chord is found from both ends, keep the one going right or down
+			if vertex.dx > 0 {
+				let mut x = vertex.x;
+				while grid.is_interior_h(x, vertex.y) {
+					x += 1;
+				}
+
+				if directions.get(&(x, vertex.y)).map(|d| d.0) == Some(-1) {
+					horizontal.push(Chord { fixed: vertex.y, from: vertex.x, to: x });
+				}
+			}
+
+			if vertex.dy > 0 {
+				let mut y = vertex.y;
+				while grid.is_interior_v(vertex.x, y) {
+					y += 1;
+				}
+
+				if directions.get(&(vertex.x, y)).map(|d| d.1) == Some(-1) {
+					vertical.push(Chord { fixed: vertex.x, from: vertex.y, to: y });
+				}
+			}
+		}
+
+		(horizontal, vertical)
+	}
+
+	/// Maximum independent set of the bipartite chords intersection graph (König's theorem).
+	fn _get_max_independent_chords(horizontal: &[Chord], vertical: &[Chord]) -> (Vec<Chord>, Vec<Chord>) {
+		let adjacency: Vec<Vec<usize>> = horizontal.iter()
+			.map(|h| (0 .. vertical.len()).filter(|v| Chord::intersects(h, &vertical[*v])).collect())
+			.collect();
+
+		let mut match_h: Vec<Option<usize>> = vec![None; horizontal.len()];
+		let mut match_v: Vec<Option<usize>> = vec![None; vertical.len()];
+
+		for start in 0 .. horizontal.len() {
+			// breadth-first search of an augmenting path
+			let mut parents: Vec<Option<usize>> = vec![None; vertical.len()];
+			let mut queue = VecDeque::new();
+			queue.push_back(start);
+
+			let mut free_v = None;
+
+			'search: while let Some(h) = queue.pop_front() {
+				for v in &adjacency[h] {
+					if parents[*v].is_some() {
+						continue;
+					}
+
+					parents[*v] = Some(h);
+
+					match match_v[*v] {
+						Some(next_h) => queue.push_back(next_h),
+						None => {
+							free_v = Some(*v);
+							break 'search;
+						},
+					}
+				}
+			}
+
+			while let Some(v) = free_v {
+				let h = parents[v].unwrap();
+				free_v = match_h[h];
+				match_h[h] = Some(v);
+				match_v[v] = Some(h);
+			}
+		}
+
+		// vertices reachable from unmatched horizontal chords by alternating paths
+		let mut reached_h = vec![false; horizontal.len()];
+		let mut reached_v = vec![false; vertical.len()];
+		let mut stack: Vec<usize> = (0 .. horizontal.len()).filter(|h| match_h[*h].is_none()).collect();
+
+		for h in &stack {
+			reached_h[*h] = true;
+		}
+
+		while let Some(h) = stack.pop() {
+			for v in &adjacency[h] {
+				if reached_v[*v] {
+					continue;
+				}
+
+				reached_v[*v] = true;
+
+				if let Some(next_h) = match_v[*v] {
+					if !reached_h[next_h] {
+						reached_h[next_h] = true;
+						stack.push(next_h);
+					}
+				}
+			}
+		}
+
+		let horizontal = horizontal.iter().zip(reached_h).filter(|(_, reached)| *reached).map(|(chord, _)| *chord).collect();
+		let vertical = vertical.iter().zip(reached_v).filter(|(_, reached)| !*reached).map(|(chord, _)| *chord).collect();
+
+		(horizontal, vertical)
+	}
+
+	fn _split_by_walls(grid: &Grid, walls: &Walls) -> SplittedComplexGeometry {
+		let mut geometry = SplittedComplexGeometry::default();
+		let mut assigned = vec![false; grid.cells.len()];
+
+		let is_free = |assigned: &[bool], x: usize, y: usize| grid.cells[y * grid.width + x] && !assigned[y * grid.width + x];
+
+		for y in 0 .. grid.height {
+			for x in 0 .. grid.width {
+				if !is_free(&assigned, x, y) {
+					continue;
+				}
+
+				let mut max_x = x;
+				while max_x + 1 < grid.width && is_free(&assigned, max_x + 1, y) && !walls.vertical.contains(&(max_x + 1, y)) {
+					max_x += 1;
+				}
+
+				let mut max_y = y;
+				while max_y + 1 < grid.height {
+					let next_y = max_y + 1;
+					let fits = (x ..= max_x).all(|cx| is_free(&assigned, cx, next_y) && !walls.horizontal.contains(&(cx, next_y))) &&
+						(x + 1 ..= max_x).all(|cx| !walls.vertical.contains(&(cx, next_y)));
+
+					if !fits {
+						break;
+					}
+
+					max_y = next_y;
+				}
+
+				for cy in y ..= max_y {
+					for cx in x ..= max_x {
+						assigned[cy * grid.width + cx] = true;
+					}
+				}
+
+				geometry.add_bbox(grid.to_bbox(x, y, max_x, max_y));
+			}
+		}
+
+		geometry
+	}
+}
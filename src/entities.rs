@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::{Error, Result};
+use crate::legend::Legend;
+use crate::types::{
+	BBox,
+	Color,
+	Point,
+	Shape,
+	ShapesLayout,
+};
+
+/// Marks a legend layer as entities: every shape of its color is exported as an entity instance
+/// instead of geometry. Limits the count of the instances, any count is allowed by default.
+///
+/// ```ron
+/// "player": (color: "#0000FF", entity: Some((min: 1, max: Some(1)))),
+/// "coin": (color: "#FFFF00", entity: Some(())),
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntityRule
+{
+	pub min: usize,
+	pub max: Option<usize>,
+}
+
+impl EntityRule
+{
+	pub fn exactly(count: usize) -> Self {
+		Self { min: count, max: Some(count) }
+	}
+
+	pub fn allows(&self, count: usize) -> bool {
+		count >= self.min && self.max.is_none_or(|max| count <= max)
+	}
+}
+
+impl fmt::Display for EntityRule
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match (self.min, self.max) {
+			(0, None) => write!(f, "any number"),
+			(min, None) => write!(f, "at least {}", min),
+			(0, Some(max)) => write!(f, "at most {}", max),
+			(min, Some(max)) if min == max => write!(f, "exactly {}", min),
+			(min, Some(max)) => write!(f, "from {} to {}", min, max),
+		}
+	}
+}
+
+/// Instance of an entity layer: spawn point, pickup, checkpoint, ...
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entity
+{
+	/// Name of the legend layer.
+	pub kind: String,
+	/// Top-left pixel of the marker.
+	pub position: Point,
+	/// Area of the marker, a single pixel for most of them.
+	pub bbox: BBox,
+}
+
+impl Entity
+{
+	pub fn new(kind: &str, shape: &Shape) -> Self {
+		Self {
+			kind: kind.to_string(),
+			position: shape.get_first_point(),
+			bbox: shape.get_bbox(),
+		}
+	}
+}
+
+impl ShapesLayout
+{
+	/// Entities of the `kind` in scanline order.
+	pub fn get_entities<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a Entity> + 'a {
+		self.entities.iter().filter(move |entity| entity.kind == kind)
+	}
+
+	/// Moves shapes of the legend entity layers into entities, ordered by kind,
+	/// and checks their counts against the layer rules. Returns entity indices by the color and index of their shapes.
+	pub(crate) fn extract_entities(&mut self, legend: &Legend) -> Result<HashMap<(Color, usize), usize>> {
+		let mut indices = HashMap::new();
+
+		for (kind, layer) in &legend.layers {
+			let rule = match layer.entity {
+				Some(rule) => rule,
+				None => continue,
+			};
+
+			let shapes = self.shapes.remove(&layer.color).unwrap_or_default();
+
+			if !rule.allows(shapes.len()) {
+				return Err(Error::EntityCount {
+					kind: kind.clone(),
+					rule,
+					points: shapes.iter().map(Shape::get_first_point).collect(),
+				});
+			}
+
+			for (index, shape) in shapes.iter().enumerate() {
+				indices.insert((layer.color, index), self.entities.len());
+				self.entities.push(Entity::new(kind, shape));
+			}
+		}
+
+		Ok(indices)
+	}
+}
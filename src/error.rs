@@ -0,0 +1,103 @@
+use std::fmt;
+
+use crate::entities::EntityRule;
+use crate::types::{
+	Color,
+	Point,
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error
+{
+	/// Reading or writing a file failed.
+	Io(std::io::Error),
+	/// The image couldn't be decoded.
+	Decode(image::ImageError),
+	/// A legend or a layout file isn't valid RON of the expected structure.
+	Parse(ron::Error),
+	/// A layout couldn't be written as RON.
+	Serialize(ron::Error),
+	/// A layout couldn't be written as JSON, or a JSON document isn't a layout.
+	Json(serde_json::Error),
+	/// A JSON document is written with a newer schema version than the supported one.
+	UnsupportedVersion(u32),
+	/// The image has no pixels.
+	EmptyImage,
+	/// The image has more pixels than the layout can address.
+	ImageTooLarge { width: u32, height: u32 },
+	/// A legend setting is out of its range.
+	InvalidLegend(String),
+	DuplicateColor { color: Color, layers: (String, String) },
+	/// A color missing in a strict legend, at the first pixel of its first shape.
+	UnknownColor { color: Color, point: Point },
+	/// Two image layers have the same name.
+	DuplicateLayer(String),
+	/// Image of the `layer` differs in size from the first image layer.
+	SizeMismatch { layer: String, size: (u32, u32), expected: (String, (u32, u32)) },
+	/// Count of the `kind` entities breaks its legend rule, `points` are positions of the found ones.
+	EntityCount { kind: String, rule: EntityRule, points: Vec<Point> },
+}
+
+impl fmt::Display for Error
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "{}", e),
+			Error::Decode(e) => write!(f, "failed to decode image: {}", e),
+			Error::Parse(e) => write!(f, "failed to parse: {}", e),
+			Error::Serialize(e) => write!(f, "failed to serialize: {}", e),
+			Error::Json(e) => write!(f, "invalid JSON layout: {}", e),
+			Error::UnsupportedVersion(version) => write!(f, "JSON schema version {} is newer than the supported {}", version, crate::json::JSON_VERSION),
+			Error::EmptyImage => write!(f, "image is empty"),
+			Error::ImageTooLarge { width, height } => write!(f, "image of {}×{} pixels is too large", width, height),
+			Error::InvalidLegend(message) => write!(f, "invalid legend: {}", message),
+			Error::DuplicateColor { color, layers } => write!(f, "color {} is used by both `{}` and `{}` layers", color, layers.0, layers.1),
+			Error::UnknownColor { color, point } => write!(f, "color {} at ({}, {}) is not in the legend", color, point.x, point.y),
+			Error::DuplicateLayer(name) => write!(f, "layer `{}` is given twice", name),
+			Error::SizeMismatch { layer, size, expected } => {
+				write!(f, "layer `{}` is {}×{} while `{}` is {}×{}", layer, size.0, size.1, expected.0, (expected.1).0, (expected.1).1)
+			},
+			Error::EntityCount { kind, rule, points } => {
+				write!(f, "expected {} `{}` entities, found {}", rule, kind, points.len())?;
+
+				for (i, point) in points.iter().enumerate() {
+					write!(f, "{} ({}, {})", if i == 0 { " at" } else { "," }, point.x, point.y)?;
+				}
+
+				Ok(())
+			},
+		}
+	}
+}
+
+impl std::error::Error for Error
+{
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::Io(e) => Some(e),
+			Error::Decode(e) => Some(e),
+			Error::Parse(e) | Error::Serialize(e) => Some(e),
+			Error::Json(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for Error
+{
+	fn from(e: std::io::Error) -> Self {
+		Error::Io(e)
+	}
+}
+
+impl From<image::ImageError> for Error
+{
+	fn from(e: image::ImageError) -> Self {
+		match e {
+			image::ImageError::IoError(e) => Error::Io(e),
+			e => Error::Decode(e),
+		}
+	}
+}
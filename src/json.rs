@@ -0,0 +1,45 @@
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+use crate::error::{Error, Result};
+
+/// Version of the JSON layout schema, see `docs/layout.schema.json`.
+/// It changes whenever a field is renamed, removed or changes its meaning.
+pub const JSON_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Document<'a, T>
+{
+	version: u32,
+	layout: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Header
+{
+	version: u32,
+}
+
+#[derive(Deserialize)]
+struct OwnedDocument<T>
+{
+	layout: T,
+}
+
+/// `layout` wrapped into a document of the current schema version.
+pub(crate) fn to_json_string<T: Serialize>(layout: &T) -> Result<String> {
+	serde_json::to_string_pretty(&Document { version: JSON_VERSION, layout }).map_err(Error::Json)
+}
+
+/// Layout of a document written with the same or an older schema version.
+pub(crate) fn from_json<T: DeserializeOwned>(json: &str) -> Result<T> {
+	let header: Header = serde_json::from_str(json).map_err(Error::Json)?;
+
+	if header.version > JSON_VERSION {
+		return Err(Error::UnsupportedVersion(header.version));
+	}
+
+	let document: OwnedDocument<T> = serde_json::from_str(json).map_err(Error::Json)?;
+
+	Ok(document.layout)
+}
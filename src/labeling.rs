@@ -0,0 +1,195 @@
+use serde::{Serialize, Deserialize};
+
+use crate::types::{
+	BBox,
+	Point,
+};
+
+pub const NO_LABEL: u32 = u32::MAX;
+
+/// Which neighbour pixels of the same color belong to one shape.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Connectivity
+{
+	/// Pixels touching by a side.
+	#[default]
+	Four,
+	/// Pixels touching by a side or a corner, so diagonal lines are one shape.
+	Eight,
+}
+
+impl Connectivity
+{
+	/// Parses `4`, `8` or a variant name.
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"4" | "four" | "Four" => Some(Connectivity::Four),
+			"8" | "eight" | "Eight" => Some(Connectivity::Eight),
+			_ => None,
+		}
+	}
+}
+
+/// Connected components of a grid, labeled in scanline order of their first cell.
+pub struct Components
+{
+	pub labels: Vec<u32>,
+	pub width: usize,
+	pub height: usize,
+	/// Index of the first cell of every component.
+	pub first_cells: Vec<usize>,
+}
+
+impl Components
+{
+	pub fn get_count(&self) -> usize {
+		self.first_cells.len()
+	}
+
+	pub fn get_label(&self, x: usize, y: usize) -> u32 {
+		self.labels[y * self.width + x]
+	}
+
+	pub fn get_first_point(&self, label: u32) -> Point {
+		let cell = self.first_cells[label as usize];
+		Point::new(cell % self.width, cell / self.width)
+	}
+
+	/// Bounding box and cells count of every component.
+	pub fn get_stats(&self) -> Vec<(BBox, usize)> {
+		let mut stats: Vec<(BBox, usize)> = self.first_cells.iter()
+			.map(|cell| {
+				let p = Point::new(cell % self.width, cell / self.width);
+				(BBox::new(&p, &p), 0)
+			})
+			.collect();
+
+		for y in 0 .. self.height {
+			for x in 0 .. self.width {
+				let label = self.get_label(x, y);
+				if label == NO_LABEL {
+					continue;
+				}
+
+				let (bbox, count) = &mut stats[label as usize];
+				bbox.min.x = bbox.min.x.min(x);
+				bbox.max.x = bbox.max.x.max(x);
+				bbox.max.y = y;
+				*count += 1;
+			}
+		}
+
+		stats
+	}
+}
+
+fn find_root(parents: &mut [u32], label: u32) -> u32 {
+	let mut root = label;
+	while parents[root as usize] != root {
+		root = parents[root as usize];
+	}
+
+	let mut label = label;
+	while parents[label as usize] != root {
+		let next = parents[label as usize];
+		parents[label as usize] = root;
+		label = next;
+	}
+
+	root
+}
+
+fn union(parents: &mut [u32], a: u32, b: u32) -> u32 {
+	let a = find_root(parents, a);
+	let b = find_root(parents, b);
+
+	let (root, child) = if a < b { (a, b) } else { (b, a) };
+	parents[child as usize] = root;
+
+	root
+}
+
+/// Two-pass union-find labeling of connected cells.
+/// Neighbour cells are connected if their keys are equal, cells with `None` key are left unlabeled.
+/// `connectivity` of a key tells if its cells touching by a corner are connected too.
+pub fn label_components<K, F, C>(width: usize, height: usize, key: F, connectivity: C) -> Components
+	where K: PartialEq, F: Fn(usize, usize) -> Option<K>, C: Fn(&K) -> Connectivity
+{
+	let mut labels = vec![NO_LABEL; width * height];
+	let mut parents: Vec<u32> = Vec::new();
+
+	let mut prev_row: Vec<Option<K>> = Vec::new();
+
+	for y in 0 .. height {
+		let row: Vec<Option<K>> = (0 .. width).map(|x| key(x, y)).collect();
+
+		for x in 0 .. width {
+			let k = match &row[x] {
+				Some(k) => k,
+				None => continue,
+			};
+
+			let i = y * width + x;
+
+			let mut neighbours = [None; 4];
+			if x > 0 && row[x - 1].as_ref() == Some(k) {
+				neighbours[0] = Some(labels[i - 1]);
+			}
+
+			if y > 0 {
+				if prev_row[x].as_ref() == Some(k) {
+					neighbours[1] = Some(labels[i - width]);
+				}
+
+				if connectivity(k) == Connectivity::Eight {
+					if x > 0 && prev_row[x - 1].as_ref() == Some(k) {
+						neighbours[2] = Some(labels[i - width - 1]);
+					}
+
+					if x + 1 < width && prev_row[x + 1].as_ref() == Some(k) {
+						neighbours[3] = Some(labels[i - width + 1]);
+					}
+				}
+			}
+
+			labels[i] = neighbours.iter().flatten()
+				.fold(None, |label, neighbour| match label {
+					Some(label) if label != *neighbour => Some(union(&mut parents, label, *neighbour)),
+					Some(label) => Some(label),
+					None => Some(*neighbour),
+				})
+				.unwrap_or_else(|| {
+					let label = parents.len() as u32;
+					parents.push(label);
+					label
+				});
+		}
+
+		prev_row = row;
+	}
+
+	// resolve provisional labels and renumber components by their first cell
+	let mut final_labels = vec![NO_LABEL; parents.len()];
+	let mut first_cells = Vec::new();
+
+	for (i, label) in labels.iter_mut().enumerate() {
+		if *label == NO_LABEL {
+			continue;
+		}
+
+		let root = find_root(&mut parents, *label) as usize;
+		if final_labels[root] == NO_LABEL {
+			final_labels[root] = first_cells.len() as u32;
+			first_cells.push(i);
+		}
+
+		*label = final_labels[root];
+	}
+
+	Components {
+		labels,
+		width,
+		height,
+		first_cells,
+	}
+}
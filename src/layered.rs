@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, HashSet};
+
+use image::RgbaImage;
+use serde::{Serialize, Deserialize};
+
+use crate::cells::{self, CellSize};
+use crate::connections::{Connection, ShapeId};
+use crate::error::{Error, Result};
+use crate::legend::Legend;
+use crate::types::{
+	Color,
+	Shape,
+	ShapesLayout,
+	Warning,
+};
+use crate::utils;
+
+/// Shapes of one of the images a level is authored in (collisions, triggers, decoration, ...).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ImageLayer<S = Shape>
+{
+	pub name: String,
+	/// Shapes of every color in scanline order of their top-left points.
+	pub shapes: BTreeMap<Color, Vec<S>>,
+	/// Shapes of the image layer and entities of the layout joined by the lines of the legend connection layers.
+	#[serde(default)]
+	pub connections: Vec<Connection>,
+	#[serde(skip)]
+	pub warnings: Vec<Warning>,
+}
+
+impl<S> ImageLayer<S>
+{
+	pub fn get_shape(&self, color: &Color, index: usize) -> Option<&S> {
+		self.shapes.get(color)?.get(index)
+	}
+}
+
+impl ShapesLayout
+{
+	pub fn get_image_layer(&self, name: &str) -> Option<&ImageLayer> {
+		self.image_layers.iter().find(|layer| layer.name == name)
+	}
+
+	/// Shapes of the legend layer `name` in the image layer `image_layer`, empty if the legend layer is known
+	/// but absent in the image.
+	pub fn get_image_layer_shapes(&self, image_layer: &str, name: &str) -> Option<&[Shape]> {
+		let layer = self.layers.get(name)?;
+		let image_layer = self.get_image_layer(image_layer)?;
+
+		Some(image_layer.shapes.get(&layer.color).map(Vec::as_slice).unwrap_or(&[]))
+	}
+
+	/// Adds the shapes of `layout` as the image layer `name`. Entities are appended to the others,
+	/// color relations and legend layers are joined with the ones of the other images.
+	fn add_image_layer(&mut self, name: &str, layout: ShapesLayout) {
+		let offset = self.entities.len();
+		let get_id = |id| match id {
+			ShapeId::Entity(index) => ShapeId::Entity(index + offset),
+			id => id,
+		};
+
+		for (color, colors) in layout.color_dependencies {
+			self.color_dependencies.entry(color).or_default().extend(colors);
+		}
+		for (color, colors) in layout.color_enclosures {
+			self.color_enclosures.entry(color).or_default().extend(colors);
+		}
+
+		self.layers.extend(layout.layers);
+		self.entities.extend(layout.entities);
+		self.width = layout.width;
+		self.height = layout.height;
+		self.cell_size = layout.cell_size;
+
+		self.image_layers.push(ImageLayer {
+			name: name.to_string(),
+			shapes: layout.shapes,
+			connections: layout.connections.iter()
+				.map(|connection| Connection { source: get_id(connection.source), target: get_id(connection.target) })
+				.collect(),
+			warnings: layout.warnings,
+		});
+	}
+}
+
+/// Processes every `(name, image)` layer on its own with `legend` and merges them into one layout
+/// with the shapes grouped by image layer in `image_layers`, the images must have the same size.
+/// An `Auto` cell size is detected once for all snapped images, so that all layers have the same pixels.
+pub fn get_layered_layout_by_images(images: &[(&str, &RgbaImage)], legend: &Legend) -> Result<ShapesLayout> {
+	let mut names = HashSet::new();
+	for (name, _) in images {
+		if !names.insert(*name) {
+			return Err(Error::DuplicateLayer(name.to_string()));
+		}
+	}
+
+	if let Some((first_name, first)) = images.first() {
+		for (name, img) in &images[1 ..] {
+			if img.dimensions() != first.dimensions() {
+				return Err(Error::SizeMismatch {
+					layer: name.to_string(),
+					size: img.dimensions(),
+					expected: (first_name.to_string(), first.dimensions()),
+				});
+			}
+		}
+	}
+
+	let cell_size = match legend.cell_size {
+		CellSize::Auto => {
+			let snapped: Vec<_> = images.iter().map(|(_, img)| utils::snap(img, legend, &mut Vec::new()).0).collect();
+			let images: Vec<&RgbaImage> = snapped.iter().map(|img| &**img).collect();
+			CellSize::Fixed(cells::detect_common_cell_size(&images, legend))
+		},
+		cell_size => cell_size,
+	};
+	let legend = Legend { cell_size, ..legend.clone() };
+
+	let mut layered = ShapesLayout::default();
+
+	for (name, img) in images {
+		let layout = utils::get_shapes_layout_by_rgba_image(img, &legend)?;
+		layered.add_image_layer(name, layout);
+	}
+
+	Ok(layered)
+}
+
+/// Layered layout of `(name, image path)` layers, see `get_layered_layout_by_images`.
+pub fn get_layered_layout(layers: &[(&str, &str)], legend: &Legend) -> Result<ShapesLayout> {
+	let images = layers.iter()
+		.map(|(_, path)| image::open(path).map(|img| img.into_rgba()))
+		.collect::<std::result::Result<Vec<_>, _>>()?;
+
+	let named: Vec<(&str, &RgbaImage)> = layers.iter().map(|(name, _)| *name).zip(&images).collect();
+
+	get_layered_layout_by_images(&named, legend)
+}
@@ -0,0 +1,412 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde_json::json;
+
+use crate::error::{Error, Result};
+use crate::legend::Legend;
+use crate::types::{
+	BBox,
+	Color,
+	Shape,
+	ShapesLayout,
+};
+
+const LDTK_VERSION: &str = "1.5.3";
+
+/// Layer of an LDtk level.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LdtkLayer
+{
+	/// Pixels of a color, `values` are `1` for the pixels of the color and `0` for the others row by row.
+	IntGrid { identifier: String, color: Color, values: Vec<u8> },
+	/// Instances of an entity named after the layer, limited to `max_count` of them.
+	Entities { identifier: String, color: Color, max_count: Option<usize>, entities: Vec<BBox> },
+}
+
+/// LDtk project of a single level without tilesets, a grid cell is a pixel of the layout.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LdtkProject
+{
+	/// Size of the level in cells.
+	pub width: usize,
+	pub height: usize,
+	/// Size of a cell in image pixels, the layout cell size.
+	pub grid_size: u32,
+	/// Layers from the top one.
+	pub layers: Vec<LdtkLayer>,
+}
+
+impl LdtkProject
+{
+	/// Entities layer of every legend entity layer, followed by an IntGrid layer of every other legend layer
+	/// and of every color missing in the legend. Connection layers are left out. IntGrid layers of a layered
+	/// layout are repeated for every image layer and prefixed with its name.
+	pub fn from_layout(layout: &ShapesLayout, legend: &Legend) -> Self {
+		let mut project = Self::new(layout.width, layout.height, layout.cell_size.unwrap_or(1));
+		let mut identifiers = HashSet::new();
+
+		project.layers = get_entity_layers(layout, legend, &mut identifiers);
+
+		if layout.image_layers.is_empty() {
+			project.layers.extend(get_int_grid_layers(layout, &layout.shapes, legend, "", &mut identifiers));
+		}
+
+		for image_layer in &layout.image_layers {
+			let prefix = format!("{}_", image_layer.name);
+			project.layers.extend(get_int_grid_layers(layout, &image_layer.shapes, legend, &prefix, &mut identifiers));
+		}
+
+		project
+	}
+
+	fn new(width: usize, height: usize, grid_size: u32) -> Self {
+		Self { width, height, grid_size, layers: Vec::new() }
+	}
+
+	pub fn to_ldtk_string(&self) -> Result<String> {
+		let mut ids = Ids::default();
+		let grid_size = self.grid_size as usize;
+		let (px_width, px_height) = (self.width * grid_size, self.height * grid_size);
+
+		let mut layer_defs = Vec::new();
+		let mut entity_defs = Vec::new();
+		let mut layer_instances = Vec::new();
+		let level_uid = ids.get_uid();
+		let level_iid = ids.get_iid();
+
+		for layer in &self.layers {
+			let layer_uid = ids.get_uid();
+			let layer_iid = ids.get_iid();
+
+			let (identifier, color, layer_type) = match layer {
+				LdtkLayer::IntGrid { identifier, color, .. } => (identifier, color, "IntGrid"),
+				LdtkLayer::Entities { identifier, color, .. } => (identifier, color, "Entities"),
+			};
+
+			let mut layer_def = get_layer_def(identifier, color, layer_type, layer_uid, self.grid_size);
+			let mut layer_instance = json!({
+				"__identifier": identifier,
+				"__type": layer_type,
+				"__cWid": self.width,
+				"__cHei": self.height,
+				"__gridSize": self.grid_size,
+				"__opacity": 1,
+				"__pxTotalOffsetX": 0,
+				"__pxTotalOffsetY": 0,
+				"__tilesetDefUid": null,
+				"__tilesetRelPath": null,
+				"iid": layer_iid,
+				"levelId": level_uid,
+				"layerDefUid": layer_uid,
+				"pxOffsetX": 0,
+				"pxOffsetY": 0,
+				"visible": true,
+				"optionalRules": [],
+				"intGridCsv": [],
+				"autoLayerTiles": [],
+				"seed": 0,
+				"overrideTilesetUid": null,
+				"gridTiles": [],
+				"entityInstances": [],
+			});
+
+			match layer {
+				LdtkLayer::IntGrid { identifier, color, values } => {
+					layer_def["intGridValues"] = json!([{
+						"value": 1,
+						"identifier": identifier,
+						"color": get_ldtk_color(color),
+						"tile": null,
+						"groupUid": 0,
+					}]);
+					layer_instance["intGridCsv"] = json!(values);
+				},
+				LdtkLayer::Entities { identifier, color, max_count, entities } => {
+					let entity_uid = ids.get_uid();
+					entity_defs.push(get_entity_def(identifier, color, entity_uid, *max_count, self.grid_size));
+
+					layer_instance["entityInstances"] = entities.iter()
+						.map(|bbox| {
+							let (x, y) = (bbox.min.x * grid_size, bbox.min.y * grid_size);
+
+							json!({
+								"__identifier": identifier,
+								"__grid": [bbox.min.x, bbox.min.y],
+								"__pivot": [0, 0],
+								"__tags": [],
+								"__tile": null,
+								"__smartColor": get_ldtk_color(color),
+								"__worldX": x,
+								"__worldY": y,
+								"iid": ids.get_iid(),
+								"width": bbox.get_width() * grid_size,
+								"height": bbox.get_height() * grid_size,
+								"defUid": entity_uid,
+								"px": [x, y],
+								"fieldInstances": [],
+							})
+						})
+						.collect();
+				},
+			}
+
+			layer_defs.push(layer_def);
+			layer_instances.push(layer_instance);
+		}
+
+		let level = json!({
+			"identifier": "Level_0",
+			"iid": level_iid,
+			"uid": level_uid,
+			"worldX": 0,
+			"worldY": 0,
+			"worldDepth": 0,
+			"pxWid": px_width,
+			"pxHei": px_height,
+			"__bgColor": "#696A79",
+			"bgColor": null,
+			"useAutoIdentifier": true,
+			"bgRelPath": null,
+			"bgPos": null,
+			"bgPivotX": 0.5,
+			"bgPivotY": 0.5,
+			"__smartColor": "#ADADB5",
+			"__bgPos": null,
+			"externalRelPath": null,
+			"fieldInstances": [],
+			"layerInstances": layer_instances,
+			"__neighbours": [],
+		});
+
+		let project = json!({
+			"__header__": {
+				"fileType": "LDtk Project JSON",
+				"app": "LDtk",
+				"doc": "https://ldtk.io/json",
+				"schema": "https://ldtk.io/files/JSON_SCHEMA.json",
+				"appAuthor": "Sebastien 'deepnight' Benard",
+				"appVersion": LDTK_VERSION,
+				"url": "https://ldtk.io",
+			},
+			"iid": ids.get_iid(),
+			"jsonVersion": LDTK_VERSION,
+			"appBuildId": 0,
+			"nextUid": ids.get_uid(),
+			"identifierStyle": "Free",
+			"toc": [],
+			"worldLayout": "Free",
+			"worldGridWidth": px_width,
+			"worldGridHeight": px_height,
+			"defaultLevelWidth": px_width,
+			"defaultLevelHeight": px_height,
+			"defaultPivotX": 0,
+			"defaultPivotY": 0,
+			"defaultGridSize": self.grid_size,
+			"defaultEntityWidth": self.grid_size,
+			"defaultEntityHeight": self.grid_size,
+			"bgColor": "#40465B",
+			"defaultLevelBgColor": "#696A79",
+			"minifyJson": false,
+			"externalLevels": false,
+			"exportTiled": false,
+			"simplifiedExport": false,
+			"imageExportMode": "None",
+			"exportLevelBg": true,
+			"pngFilePattern": null,
+			"backupOnSave": false,
+			"backupLimit": 10,
+			"backupRelPath": null,
+			"levelNamePattern": "Level_%idx",
+			"tutorialDesc": null,
+			"customCommands": [],
+			"flags": [],
+			"defs": {
+				"layers": layer_defs,
+				"entities": entity_defs,
+				"tilesets": [],
+				"enums": [],
+				"externalEnums": [],
+				"levelFields": [],
+			},
+			"levels": [level],
+			"worlds": [],
+			"dummyWorldIid": ids.get_iid(),
+		});
+
+		serde_json::to_string_pretty(&project).map_err(Error::Json)
+	}
+}
+
+/// Unique ids of the project definitions and instances.
+#[derive(Default)]
+struct Ids
+{
+	uid: u32,
+	iid: u64,
+}
+
+impl Ids
+{
+	fn get_uid(&mut self) -> u32 {
+		self.uid += 1;
+		self.uid
+	}
+
+	/// UUID-formatted instance id, the same ones for the same project.
+	fn get_iid(&mut self) -> String {
+		self.iid += 1;
+		format!("00000000-0000-4000-8000-{:012x}", self.iid)
+	}
+}
+
+fn get_entity_layers(layout: &ShapesLayout, legend: &Legend, identifiers: &mut HashSet<String>) -> Vec<LdtkLayer> {
+	legend.layers.iter()
+		.filter(|(_, layer)| !layer.connection)
+		.filter_map(|(name, layer)| layer.entity.map(|rule| LdtkLayer::Entities {
+			identifier: get_identifier(name, identifiers),
+			color: layer.color,
+			max_count: rule.max,
+			entities: layout.get_entities(name).map(|entity| entity.bbox).collect(),
+		}))
+		.collect()
+}
+
+/// IntGrid layer of every legend layer but the entity and connection ones, and of every color of `shapes`
+/// missing in the legend.
+fn get_int_grid_layers(layout: &ShapesLayout, shapes: &BTreeMap<Color, Vec<Shape>>, legend: &Legend, prefix: &str, identifiers: &mut HashSet<String>) -> Vec<LdtkLayer> {
+	let mut layers = Vec::new();
+
+	for (name, layer) in &legend.layers {
+		if layer.connection || layer.entity.is_some() {
+			continue;
+		}
+
+		let identifier = get_identifier(&format!("{}{}", prefix, name), identifiers);
+		layers.push(get_int_grid_layer(layout, shapes, identifier, &layer.color));
+	}
+
+	for color in shapes.keys() {
+		if legend.get_layer_by_color(color).is_none() {
+			let identifier = get_identifier(&format!("{}{}", prefix, color), identifiers);
+			layers.push(get_int_grid_layer(layout, shapes, identifier, color));
+		}
+	}
+
+	layers
+}
+
+fn get_int_grid_layer(layout: &ShapesLayout, shapes: &BTreeMap<Color, Vec<Shape>>, identifier: String, color: &Color) -> LdtkLayer {
+	let mut values = vec![0; layout.width * layout.height];
+
+	for shape in shapes.get(color).into_iter().flatten() {
+		for point in shape.get_covered_points() {
+			values[point.y * layout.width + point.x] = 1;
+		}
+	}
+
+	LdtkLayer::IntGrid { identifier, color: *color, values }
+}
+
+/// LDtk identifier of `name`: letters, digits and underscores not starting with a digit,
+/// with a number appended if it's already taken.
+fn get_identifier(name: &str, identifiers: &mut HashSet<String>) -> String {
+	let mut identifier: String = name.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+		.collect();
+
+	if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+		identifier.insert(0, '_');
+	}
+
+	let mut unique = identifier.clone();
+	let mut index = 2;
+	while identifiers.contains(&unique) {
+		unique = format!("{}_{}", identifier, index);
+		index += 1;
+	}
+
+	identifiers.insert(unique.clone());
+	unique
+}
+
+/// LDtk colors have no alpha.
+fn get_ldtk_color(color: &Color) -> String {
+	format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b)
+}
+
+fn get_layer_def(identifier: &str, color: &Color, layer_type: &str, uid: u32, grid_size: u32) -> serde_json::Value {
+	json!({
+		"__type": layer_type,
+		"identifier": identifier,
+		"type": layer_type,
+		"uid": uid,
+		"doc": null,
+		"uiColor": get_ldtk_color(color),
+		"gridSize": grid_size,
+		"guideGridWid": 0,
+		"guideGridHei": 0,
+		"displayOpacity": 1,
+		"inactiveOpacity": 1,
+		"hideInList": false,
+		"hideFieldsWhenInactive": false,
+		"canSelectWhenInactive": true,
+		"renderInWorldView": true,
+		"pxOffsetX": 0,
+		"pxOffsetY": 0,
+		"parallaxFactorX": 0,
+		"parallaxFactorY": 0,
+		"parallaxScaling": true,
+		"requiredTags": [],
+		"excludedTags": [],
+		"autoTilesKilledByOtherLayerUid": null,
+		"uiFilterTags": [],
+		"useAsyncRender": false,
+		"intGridValues": [],
+		"intGridValuesGroups": [],
+		"autoRuleGroups": [],
+		"autoSourceLayerDefUid": null,
+		"tilesetDefUid": null,
+		"tilePivotX": 0,
+		"tilePivotY": 0,
+		"biomeFieldUid": null,
+	})
+}
+
+fn get_entity_def(identifier: &str, color: &Color, uid: u32, max_count: Option<usize>, grid_size: u32) -> serde_json::Value {
+	json!({
+		"identifier": identifier,
+		"uid": uid,
+		"tags": [],
+		"exportToToc": false,
+		"allowOutOfBounds": false,
+		"doc": null,
+		"width": grid_size,
+		"height": grid_size,
+		"resizableX": true,
+		"resizableY": true,
+		"minWidth": null,
+		"maxWidth": null,
+		"minHeight": null,
+		"maxHeight": null,
+		"keepAspectRatio": false,
+		"tileOpacity": 1,
+		"fillOpacity": 1,
+		"lineOpacity": 1,
+		"hollow": false,
+		"color": get_ldtk_color(color),
+		"renderMode": "Rectangle",
+		"showName": true,
+		"tilesetId": null,
+		"tileRenderMode": "FitInside",
+		"tileRect": null,
+		"uiTileRect": null,
+		"nineSliceBorders": [],
+		// no limit is 0
+		"maxCount": max_count.unwrap_or(0),
+		"limitScope": "PerLevel",
+		"limitBehavior": "MoveLastOne",
+		"pivotX": 0,
+		"pivotY": 0,
+		"fieldDefs": [],
+	})
+}
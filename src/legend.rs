@@ -0,0 +1,266 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::cells::CellSize;
+use crate::connections::{Connection, ShapeId};
+use crate::decomposition::Decomposition;
+use crate::entities::EntityRule;
+use crate::error::{Error, Result};
+use crate::labeling::Connectivity;
+use crate::palette::{PaletteSource, Snapping};
+use crate::world::Transform;
+use crate::types::{
+	Color,
+	Layer,
+	Point,
+	ShapesLayout,
+	Warning,
+};
+
+pub type Properties = BTreeMap<String, Property>;
+
+/// Arbitrary gameplay property attached to a legend layer (`solid`, `damage`, `friction`, ...).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Property
+{
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	String(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LegendLayer
+{
+	pub color: Color,
+	#[serde(default)]
+	pub properties: Properties,
+	/// Overrides the legend decomposition for this layer.
+	#[serde(default)]
+	pub decomposition: Option<Decomposition>,
+	/// Overrides the legend connectivity for this layer.
+	#[serde(default)]
+	pub connectivity: Option<Connectivity>,
+	/// Shapes of this layer are exported as entities.
+	#[serde(default)]
+	pub entity: Option<EntityRule>,
+	/// Lines of this layer connect the shapes they join instead of being shapes.
+	#[serde(default)]
+	pub connection: bool,
+}
+
+/// Maps pixel colors of a layout image to named gameplay layers and configures their processing.
+/// A legend without layers only configures processing and names no colors.
+///
+/// ```ron
+/// (
+///     strict: true,
+///     background: ["#FFFFFF", "#FF00FF"],
+///     alpha_threshold: 128,
+///     decomposition: RowMerge,
+///     layers: {
+///         "water": (color: "#2B9384", properties: {"solid": false, "friction": 0.2}),
+///         "rock": (color: "#191919", properties: {"solid": true}, decomposition: Some(MinimumRectangles)),
+///         "vines": (color: "#2F7D32", connectivity: Some(Eight)),
+///     },
+/// )
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Legend
+{
+	/// Unknown colors are errors instead of warnings.
+	pub strict: bool,
+	/// Colors of empty pixels, compared without alpha.
+	pub background: Vec<Color>,
+	/// Pixels with a lower alpha are empty.
+	pub alpha_threshold: u8,
+	/// Alpha of solid pixels is a part of their color, so pixels differing only by alpha
+	/// form different shapes. Otherwise layout colors are opaque.
+	pub keep_alpha: bool,
+	/// Snaps pixel colors to a palette before the shapes are found.
+	pub snapping: Option<Snapping>,
+	/// Every block of `cell_size` × `cell_size` pixels is one pixel of the layout.
+	pub cell_size: CellSize,
+	/// Mapping of the layout into world coordinates, the layout is exported in world space if it's set.
+	pub transform: Option<Transform>,
+	/// How complex shapes are split into boxes.
+	pub decomposition: Decomposition,
+	/// Which pixels of a color are grouped into one shape.
+	pub connectivity: Connectivity,
+	pub layers: BTreeMap<String, LegendLayer>,
+}
+
+impl Default for Legend
+{
+	fn default() -> Self {
+		Self {
+			strict: false,
+			background: vec![Color::WHITE],
+			alpha_threshold: 255,
+			keep_alpha: false,
+			snapping: None,
+			cell_size: CellSize::default(),
+			transform: None,
+			decomposition: Decomposition::default(),
+			connectivity: Connectivity::default(),
+			layers: BTreeMap::new(),
+		}
+	}
+}
+
+impl Legend
+{
+	pub fn from_ron(ron: &str) -> Result<Self> {
+		let legend: Self = ron::de::from_str(ron).map_err(Error::Parse)?;
+		legend.validate()?;
+
+		Ok(legend)
+	}
+
+	pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let ron = std::fs::read_to_string(path)?;
+		Self::from_ron(&ron)
+	}
+
+	pub fn validate(&self) -> Result<()> {
+		let mut names_by_color: HashMap<Color, &String> = HashMap::new();
+
+		for (name, layer) in &self.layers {
+			if Color::from_hex(name).is_some() {
+				return Err(Error::InvalidLegend(format!("layer name `{}` is reserved for the color missing in the legend", name)));
+			}
+
+			if let Some(other) = names_by_color.insert(layer.color, name) {
+				return Err(Error::DuplicateColor { color: layer.color, layers: (other.clone(), name.clone()) });
+			}
+
+			if layer.entity.is_some() && layer.connection {
+				return Err(Error::InvalidLegend(format!("`{}` layer can't be both entities and connections", name)));
+			}
+
+			if let Some(EntityRule { min, max: Some(max) }) = layer.entity {
+				if min > max {
+					return Err(Error::InvalidLegend(format!("`{}` entities minimum {} is greater than the maximum {}", name, min, max)));
+				}
+			}
+		}
+
+		if let Some(snapping) = &self.snapping {
+			if snapping.palette == PaletteSource::KMeans(0) {
+				return Err(Error::InvalidLegend("k-means palette must have at least one color".to_string()));
+			}
+
+			if snapping.tolerance.is_nan() || snapping.tolerance < 0.0 {
+				return Err(Error::InvalidLegend(format!("snapping tolerance {} must be a non-negative number", snapping.tolerance)));
+			}
+		}
+
+		if self.cell_size == CellSize::Fixed(0) {
+			return Err(Error::InvalidLegend("cell size must be positive".to_string()));
+		}
+
+		if let Some(transform) = &self.transform {
+			if !transform.scale.is_normal() {
+				return Err(Error::InvalidLegend(format!("transform scale {} must be a non-zero number", transform.scale)));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Color of a solid pixel, `None` for empty ones.
+	pub fn get_pixel_color(&self, rgba: &[u8; 4]) -> Option<Color> {
+		let color = Color::new(rgba[0], rgba[1], rgba[2]);
+
+		if rgba[3] < self.alpha_threshold || self.background.iter().any(|background| background.to_opaque() == color) {
+			None
+		} else if self.keep_alpha {
+			Some(Color::new_rgba(rgba[0], rgba[1], rgba[2], rgba[3]))
+		} else {
+			Some(color)
+		}
+	}
+
+	pub fn get_layer_by_color(&self, color: &Color) -> Option<(&String, &LegendLayer)> {
+		self.layers.iter().find(|(_, layer)| layer.color == *color)
+	}
+
+	pub fn get_decomposition(&self, color: &Color) -> Decomposition {
+		self.get_layer_by_color(color)
+			.and_then(|(_, layer)| layer.decomposition)
+			.unwrap_or(self.decomposition)
+	}
+
+	pub fn get_connectivity(&self, color: &Color) -> Connectivity {
+		self.get_layer_by_color(color)
+			.and_then(|(_, layer)| layer.connectivity)
+			.unwrap_or(self.connectivity)
+	}
+
+	/// Connectivity of the layers overriding the legend one, by color.
+	pub fn get_connectivities(&self) -> HashMap<Color, Connectivity> {
+		self.layers.values()
+			.filter_map(|layer| layer.connectivity.map(|connectivity| (layer.color, connectivity)))
+			.collect()
+	}
+}
+
+impl ShapesLayout
+{
+	/// Names the layout colors after `legend` layers.
+	/// Colors missing in the legend fail a strict legend and are reported as warnings otherwise.
+	pub fn apply_legend(&mut self, legend: &Legend) -> Result<()> {
+		legend.validate()?;
+
+		if legend.layers.is_empty() {
+			return Ok(());
+		}
+
+		let mut unknown_colors: Vec<(Color, Point)> = Vec::new();
+
+		for (color, shapes) in &self.shapes {
+			if legend.get_layer_by_color(color).is_none() {
+				let point = shapes.iter().map(|shape| shape.get_first_point()).min().unwrap_or_default();
+				unknown_colors.push((*color, point));
+			}
+		}
+
+		unknown_colors.sort_by_key(|(_, point)| *point);
+
+		if legend.strict {
+			if let Some((color, point)) = unknown_colors.first() {
+				return Err(Error::UnknownColor { color: *color, point: *point });
+			}
+		}
+
+		self.warnings.extend(unknown_colors.into_iter().map(|(color, point)| Warning::UnknownColor { color, point }));
+
+		let pairs = self.extract_connections(legend);
+		let entities = self.extract_entities(legend)?;
+
+		let get_id = |(color, index): (Color, usize)| match entities.get(&(color, index)) {
+			Some(entity) => ShapeId::Entity(*entity),
+			None => ShapeId::Shape(color, index),
+		};
+
+		self.connections = pairs.into_iter()
+			.map(|(source, target)| Connection { source: get_id(source), target: get_id(target) })
+			.collect();
+
+		self.layers = legend.layers.iter()
+			.map(|(name, layer)| (name.clone(), Layer { color: layer.color, properties: layer.properties.clone() }))
+			.collect();
+
+		for color in self.shapes.keys() {
+			if legend.get_layer_by_color(color).is_none() {
+				self.layers.insert(color.to_string(), Layer { color: *color, properties: Properties::new() });
+			}
+		}
+
+		Ok(())
+	}
+}
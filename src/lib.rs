@@ -1,10 +1,72 @@
 #![allow(dead_code)]
 
+mod cells;
+mod connections;
+mod contour;
+mod decomposition;
+mod entities;
+mod error;
+mod labeling;
+mod json;
+mod layered;
+mod ldtk;
+mod legend;
+mod palette;
+mod render;
+mod svg;
+mod tiled;
 mod types;
 mod utils;
+mod world;
 
+#[cfg(test)]
+mod tests;
+
+pub use crate::cells::*;
+pub use crate::connections::*;
+pub use crate::contour::*;
+pub use crate::decomposition::*;
+pub use crate::entities::*;
+pub use crate::error::{Error, Result};
+pub use crate::json::JSON_VERSION;
+pub use crate::labeling::Connectivity;
+pub use crate::layered::*;
+pub use crate::ldtk::*;
+pub use crate::legend::*;
+pub use crate::palette::*;
+pub use crate::render::*;
+pub use crate::svg::*;
+pub use crate::tiled::*;
 pub use crate::types::*;
+pub use crate::world::*;
 
-pub fn get_shapes_layout(img_path: &str) -> Result<ShapesLayout, image::ImageError> {
+pub fn get_shapes_layout(img_path: &str) -> Result<ShapesLayout> {
 	utils::get_shapes_layout(img_path)
-}
\ No newline at end of file
+}
+
+pub fn get_shapes_layout_with_legend(img_path: &str, legend: &Legend) -> Result<ShapesLayout> {
+	utils::get_shapes_layout_with_legend(img_path, legend)
+}
+
+pub fn get_shapes_layout_by_rgba_image(img: &image::RgbaImage, legend: &Legend) -> Result<ShapesLayout> {
+	utils::get_shapes_layout_by_rgba_image(img, legend)
+}
+
+pub fn get_shapes_layout_by_dynamic_image(img: &image::DynamicImage, legend: &Legend) -> Result<ShapesLayout> {
+	utils::get_shapes_layout_by_dynamic_image(img, legend)
+}
+
+/// `img` as the layout is built from it with `legend`: colors snapped to the palette and cells downsampled to pixels.
+pub fn get_layout_image<'a>(img: &'a image::RgbaImage, legend: &Legend) -> Result<std::borrow::Cow<'a, image::RgbaImage>> {
+	utils::get_layout_image(img, legend)
+}
+
+/// Layout of encoded image `bytes`, the format is guessed from the contents if `None`.
+pub fn get_shapes_layout_by_bytes(bytes: &[u8], format: Option<image::ImageFormat>, legend: &Legend) -> Result<ShapesLayout> {
+	utils::get_shapes_layout_by_bytes(bytes, format, legend)
+}
+
+/// Layout of an encoded image read from `reader`, the format is guessed from the contents if `None`.
+pub fn get_shapes_layout_by_reader<R: std::io::Read + std::io::Seek>(reader: R, format: Option<image::ImageFormat>, legend: &Legend) -> Result<ShapesLayout> {
+	utils::get_shapes_layout_by_reader(reader, format, legend)
+}
@@ -1,13 +1,256 @@
+mod cli;
 
-mod types;
-mod utils;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
 
+use cli::{Args, Command, Output, OutputFormat, Verbosity, EXIT_FAILURE};
+use image::RgbaImage;
+use layouter::{CellSize, Color, LdtkProject, Legend, Shape, ShapesLayout, TiledMap, Warning};
 
-#[cfg(test)]
-mod tests;
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Ok(Command::Version) => {
+            println!("layouter {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        },
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, cli::USAGE);
+            return ExitCode::from(error.exit_code());
+        },
+    };
 
-fn main() {
-    let shapes = utils::get_shapes_layout("assets/color_sample.png").unwrap();
+    let mut legend = match &args.legend {
+        Some(path) => match Legend::read(path) {
+            Ok(legend) => legend,
+            Err(e) => {
+                eprintln!("error: `{}`: {}", path.display(), e);
+                return ExitCode::from(EXIT_FAILURE);
+            },
+        },
+        None => Legend::default(),
+    };
 
-    println!("{:#?}", shapes);
+    if let Some(decomposition) = args.decomposition {
+        legend.decomposition = decomposition;
+    }
+
+    if let Some(connectivity) = args.connectivity {
+        legend.connectivity = connectivity;
+    }
+
+    if !args.background.is_empty() {
+        legend.background = args.background.clone();
+    }
+
+    if let Some(alpha_threshold) = args.alpha_threshold {
+        legend.alpha_threshold = alpha_threshold;
+    }
+
+    legend.keep_alpha |= args.keep_alpha;
+
+    if args.scale.is_some() || args.origin.is_some() || args.flip_y || args.anchor.is_some() {
+        let transform = legend.transform.get_or_insert_with(Default::default);
+        transform.scale = args.scale.unwrap_or(transform.scale);
+        transform.origin = args.origin.unwrap_or(transform.origin);
+        transform.flip_y |= args.flip_y;
+        transform.anchor = args.anchor.unwrap_or(transform.anchor);
+    }
+
+    if let Some(cell_size) = args.cell_size {
+        legend.cell_size = cell_size;
+    }
+
+    if args.snap_tolerance.is_some() || args.palette.is_some() || args.color_space.is_some() {
+        let snapping = legend.snapping.get_or_insert_with(Default::default);
+        snapping.tolerance = args.snap_tolerance.unwrap_or(snapping.tolerance);
+        snapping.palette = args.palette.unwrap_or(snapping.palette);
+        snapping.color_space = args.color_space.unwrap_or(snapping.color_space);
+    }
+
+    let mut failed = false;
+
+    if !args.layers.is_empty() {
+        if let Err(message) = process_layered(&args, &legend) {
+            eprintln!("error: {}", message);
+            failed = true;
+        }
+    }
+
+    for input in &args.inputs {
+        if let Err(message) = process(&args, &legend, input) {
+            eprintln!("error: {}", message);
+            failed = true;
+        }
+    }
+
+    if failed {
+        ExitCode::from(EXIT_FAILURE)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn process(args: &Args, legend: &Legend, input: &Path) -> Result<(), String> {
+    let img = read_image(input)?;
+    let layout = layouter::get_shapes_layout_by_rgba_image(&img, legend)
+        .map_err(|e| format!("`{}`: {}", input.display(), e))?;
+
+    print_warnings(args, input, &layout.warnings);
+
+    let contents = serialize(args, &layout, std::slice::from_ref(&img), legend)
+        .map_err(|e| format!("`{}`: {}", input.display(), e))?;
+
+    write_output(args, input, &contents)?;
+
+    if args.verbosity >= Verbosity::Verbose {
+        print_details(&layout, "    ");
+    }
+
+    Ok(())
+}
+
+/// Converts all `--layer` images into one layered level, written next to the first image.
+fn process_layered(args: &Args, legend: &Legend) -> Result<(), String> {
+    let images = args.layers.iter()
+        .map(|(_, path)| read_image(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let layers: Vec<(&str, &RgbaImage)> = args.layers.iter().map(|(name, _)| name.as_str()).zip(&images).collect();
+
+    let input = &args.layers[0].1;
+
+    let layout = layouter::get_layered_layout_by_images(&layers, legend)
+        .map_err(|e| format!("`{}`: {}", input.display(), e))?;
+
+    for (layer, (_, path)) in layout.image_layers.iter().zip(&args.layers) {
+        print_warnings(args, path, &layer.warnings);
+    }
+
+    // the cell size detected for the layers, so that every image is downsampled like its layer
+    let legend = Legend { cell_size: CellSize::Fixed(layout.cell_size.unwrap_or(1)), ..legend.clone() };
+    let contents = serialize(args, &layout, &images, &legend)
+        .map_err(|e| format!("`{}`: {}", input.display(), e))?;
+
+    write_output(args, input, &contents)?;
+
+    if args.verbosity >= Verbosity::Verbose {
+        print_details(&layout, "    ");
+    }
+
+    Ok(())
+}
+
+/// Image of `path`, or of stdin for `-`.
+fn read_image(path: &Path) -> Result<RgbaImage, String> {
+    let img = if path == Path::new(cli::STDIN) {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes)
+            .map_err(|e| format!("failed to read stdin: {}", e))?;
+
+        image::load_from_memory(&bytes)
+    } else {
+        image::open(path)
+    };
+
+    img.map(|img| img.into_rgba())
+        .map_err(|e| format!("`{}`: {}", path.display(), e))
+}
+
+fn print_warnings(args: &Args, input: &Path, warnings: &[Warning]) {
+    if args.verbosity >= Verbosity::Normal {
+        for warning in warnings {
+            eprintln!("warning: `{}`: {}", input.display(), warning);
+        }
+    }
+}
+
+fn write_output(args: &Args, input: &Path, contents: &str) -> Result<(), String> {
+    match args.get_output(input) {
+        Output::Stdout => {
+            std::io::stdout().write_all(contents.as_bytes())
+                .map_err(|e| format!("failed to write to stdout: {}", e))?;
+        },
+        Output::File(path) => {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)
+                    .map_err(|e| format!("failed to create `{}`: {}", dir.display(), e))?;
+            }
+
+            fs::write(&path, contents)
+                .map_err(|e| format!("failed to write `{}`: {}", path.display(), e))?;
+
+            if args.verbosity >= Verbosity::Normal {
+                eprintln!("{} -> {}", input.display(), path.display());
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn print_details(layout: &ShapesLayout, indent: &str) {
+    if let Some(cell_size) = layout.cell_size {
+        eprintln!("{}{}×{} pixels cells", indent, cell_size, cell_size);
+    }
+
+    if let Some(report) = &layout.snap_report {
+        let out_of_tolerance_count: usize = report.out_of_tolerance.values().map(Vec::len).sum();
+        eprintln!("{}{} pixels snapped to {} colors, {} out of tolerance", indent, report.snapped_count, report.palette.len(), out_of_tolerance_count);
+    }
+
+    print_shapes(&layout.shapes, indent);
+
+    for layer in &layout.image_layers {
+        eprintln!("{}{}:", indent, layer.name);
+        print_shapes(&layer.shapes, &format!("{}    ", indent));
+
+        if !layer.connections.is_empty() {
+            eprintln!("{}    {} connections", indent, layer.connections.len());
+        }
+    }
+
+    if !layout.entities.is_empty() {
+        eprintln!("{}{} entities", indent, layout.entities.len());
+    }
+
+    if !layout.connections.is_empty() {
+        eprintln!("{}{} connections", indent, layout.connections.len());
+    }
+}
+
+fn print_shapes(shapes: &BTreeMap<Color, Vec<Shape>>, indent: &str) {
+    for (color, shapes) in shapes {
+        let holes_count: usize = shapes.iter().map(|shape| shape.get_holes().len()).sum();
+        eprintln!("{}{}: {} shapes, {} holes", indent, color, shapes.len(), holes_count);
+    }
+}
+
+/// The layout in the output format, in world coordinates if the legend has a transform.
+/// Tiled maps, LDtk projects and SVG pictures are always in image pixels.
+/// SVG pictures draw the pixels of `images`, one per image layer of a layered layout, under the shapes.
+fn serialize(args: &Args, layout: &ShapesLayout, images: &[RgbaImage], legend: &Legend) -> layouter::Result<String> {
+    match (args.format, legend.transform.as_ref()) {
+        (OutputFormat::Ron, None) => layout.to_ron_string(),
+        (OutputFormat::Ron, Some(transform)) => transform.apply(layout).to_ron_string(),
+        (OutputFormat::Json, None) => layout.to_json_string(),
+        (OutputFormat::Json, Some(transform)) => transform.apply(layout).to_json_string(),
+        (OutputFormat::Tmx, _) => Ok(TiledMap::from_layout(layout, args.tiled_complex).to_tmx_string()),
+        (OutputFormat::Tmj, _) => TiledMap::from_layout(layout, args.tiled_complex).to_tmj_string(),
+        (OutputFormat::Ldtk, _) => LdtkProject::from_layout(layout, legend).to_ldtk_string(),
+        (OutputFormat::Svg, _) => {
+            let sources = images.iter()
+                .map(|img| layouter::get_layout_image(img, legend))
+                .collect::<layouter::Result<Vec<_>>>()?;
+            let sources: Vec<&RgbaImage> = sources.iter().map(|source| source.as_ref()).collect();
+
+            Ok(layout.to_svg_string(&sources, &args.svg))
+        },
+    }
 }
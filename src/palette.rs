@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, HashMap};
+
+use image::RgbaImage;
+use serde::{Serialize, Deserialize};
+
+use crate::legend::Legend;
+use crate::types::{
+	Color,
+	Point,
+};
+
+/// Space colors are compared in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorSpace
+{
+	/// Euclidean distance of RGB channels.
+	#[default]
+	Rgb,
+	/// Perceptual CIE76 distance in the CIELAB space.
+	Lab,
+}
+
+impl ColorSpace
+{
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"rgb" | "Rgb" => Some(ColorSpace::Rgb),
+			"lab" | "Lab" => Some(ColorSpace::Lab),
+			_ => None,
+		}
+	}
+
+	fn to_coordinates(self, color: &Color) -> [f64; 3] {
+		match self {
+			ColorSpace::Rgb => [color.r as f64, color.g as f64, color.b as f64],
+			ColorSpace::Lab => to_lab(color),
+		}
+	}
+
+	pub fn get_distance(self, color: &Color, other: &Color) -> f64 {
+		get_distance(&self.to_coordinates(color), &self.to_coordinates(other))
+	}
+}
+
+fn get_distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+	a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f64>().sqrt()
+}
+
+/// sRGB color in the CIELAB space with the D65 white point.
+fn to_lab(color: &Color) -> [f64; 3] {
+	let linear = |c: u8| {
+		let c = c as f64 / 255.0;
+		if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+	};
+
+	let (r, g, b) = (linear(color.r), linear(color.g), linear(color.b));
+
+	let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+	let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+	let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+	let f = |t: f64| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+
+	[116.0 * f(y) - 16.0, 500.0 * (f(x) - f(y)), 200.0 * (f(y) - f(z))]
+}
+
+/// Colors pixels are snapped to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteSource
+{
+	/// Colors of the legend layers and background.
+	#[default]
+	Legend,
+	/// Legend background and the given count of colors clustered from the image by k-means.
+	KMeans(usize),
+}
+
+impl PaletteSource
+{
+	/// Parses `legend` or a colors count.
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"legend" | "Legend" => Some(PaletteSource::Legend),
+			_ => name.parse().ok().filter(|k| *k > 0).map(PaletteSource::KMeans),
+		}
+	}
+}
+
+/// Preprocessing of anti-aliased or compressed images: every solid pixel takes the nearest palette color.
+///
+/// ```ron
+/// snapping: Some((palette: KMeans(6), color_space: Lab, tolerance: 10.0)),
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapping
+{
+	pub palette: PaletteSource,
+	pub color_space: ColorSpace,
+	/// Largest distance from a pixel to its palette color, further pixels are kept as is and reported.
+	pub tolerance: f64,
+}
+
+impl Default for Snapping
+{
+	fn default() -> Self {
+		Self {
+			palette: PaletteSource::default(),
+			color_space: ColorSpace::default(),
+			tolerance: 48.0,
+		}
+	}
+}
+
+/// Outcome of snapping an image to a palette.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SnapReport
+{
+	pub palette: Vec<Color>,
+	/// Count of pixels which changed their color.
+	pub snapped_count: usize,
+	/// Pixels further than the tolerance from the palette, by their color.
+	pub out_of_tolerance: BTreeMap<Color, Vec<Point>>,
+}
+
+impl Snapping
+{
+	/// Snaps colors of solid `img` pixels, alpha of pixels is kept.
+	pub fn apply(&self, img: &mut RgbaImage, legend: &Legend) -> SnapReport {
+		let palette = match self.palette {
+			PaletteSource::Legend => get_legend_palette(legend),
+			PaletteSource::KMeans(count) => {
+				let mut palette = get_kmeans_palette(img, legend, count, self.color_space);
+				palette.extend(legend.background.iter().map(Color::to_opaque));
+				palette
+			},
+		};
+
+		let coordinates: Vec<[f64; 3]> = palette.iter().map(|color| self.color_space.to_coordinates(color)).collect();
+
+		let mut report = SnapReport::default();
+		let mut snapped_colors: HashMap<Color, Option<Color>> = HashMap::new();
+
+		for (x, y, pixel) in img.enumerate_pixels_mut() {
+			if pixel[3] < legend.alpha_threshold {
+				continue;
+			}
+
+			let color = Color::new(pixel[0], pixel[1], pixel[2]);
+
+			let snapped = *snapped_colors.entry(color).or_insert_with(|| {
+				let point = self.color_space.to_coordinates(&color);
+
+				coordinates.iter().enumerate()
+					.map(|(i, c)| (i, get_distance(&point, c)))
+					.min_by(|a, b| a.1.total_cmp(&b.1))
+					.filter(|(_, distance)| *distance <= self.tolerance)
+					.map(|(i, _)| palette[i])
+			});
+
+			match snapped {
+				Some(snapped) if snapped != color => {
+					pixel.0 = [snapped.r, snapped.g, snapped.b, pixel[3]];
+					report.snapped_count += 1;
+				},
+				Some(_) => {},
+				None => report.out_of_tolerance.entry(color).or_default().push(Point::new(x, y)),
+			}
+		}
+
+		report.palette = palette;
+		report
+	}
+}
+
+fn get_legend_palette(legend: &Legend) -> Vec<Color> {
+	legend.layers.values().map(|layer| layer.color.to_opaque())
+		.chain(legend.background.iter().map(Color::to_opaque))
+		.collect()
+}
+
+/// `count` colors of the image solid pixels clustered by k-means, every one is the image color nearest to its cluster center.
+/// Clusters start from the most frequent color and the colors furthest from the chosen ones, so the result is deterministic.
+pub fn get_kmeans_palette(img: &RgbaImage, legend: &Legend, count: usize, color_space: ColorSpace) -> Vec<Color> {
+	let mut histogram: BTreeMap<Color, usize> = BTreeMap::new();
+	for pixel in img.pixels() {
+		if let Some(color) = legend.get_pixel_color(&pixel.0) {
+			*histogram.entry(color.to_opaque()).or_default() += 1;
+		}
+	}
+
+	let colors: Vec<(Color, [f64; 3], f64)> = histogram.iter()
+		.map(|(color, count)| (*color, color_space.to_coordinates(color), *count as f64))
+		.collect();
+
+	if colors.len() <= count {
+		return colors.iter().map(|(color, _, _)| *color).collect();
+	}
+
+	let mut centers: Vec<[f64; 3]> = Vec::new();
+	if let Some((_, first, _)) = colors.iter().max_by(|a, b| a.2.total_cmp(&b.2)) {
+		centers.push(*first);
+	}
+
+	while centers.len() < count {
+		let furthest = colors.iter()
+			.map(|(_, c, _)| (c, centers.iter().map(|center| get_distance(c, center)).fold(f64::INFINITY, f64::min)))
+			.max_by(|a, b| a.1.total_cmp(&b.1))
+			.map(|(c, _)| *c)
+			.unwrap();
+
+		centers.push(furthest);
+	}
+
+	let get_nearest = |centers: &[[f64; 3]], c: &[f64; 3]| -> usize {
+		centers.iter().enumerate()
+			.map(|(i, center)| (i, get_distance(c, center)))
+			.min_by(|a, b| a.1.total_cmp(&b.1))
+			.map(|(i, _)| i)
+			.unwrap()
+	};
+
+	for _ in 0 .. 32 {
+		let mut sums = vec![([0.0; 3], 0.0); count];
+
+		for (_, c, weight) in &colors {
+			let (sum, total) = &mut sums[get_nearest(&centers, c)];
+			for i in 0 .. 3 {
+				sum[i] += c[i] * weight;
+			}
+			*total += weight;
+		}
+
+		let next: Vec<[f64; 3]> = sums.iter().zip(&centers)
+			.map(|((sum, total), center)| if *total > 0.0 { [sum[0] / total, sum[1] / total, sum[2] / total] } else { *center })
+			.collect();
+
+		let converged = next == centers;
+		centers = next;
+
+		if converged {
+			break;
+		}
+	}
+
+	let mut palette: Vec<Color> = centers.iter()
+		.map(|center| {
+			colors.iter()
+				.min_by(|a, b| get_distance(&a.1, center).total_cmp(&get_distance(&b.1, center)))
+				.map(|(color, _, _)| *color)
+				.unwrap()
+		})
+		.collect();
+
+	palette.sort();
+	palette.dedup();
+	palette
+}
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+
+use image::{Rgba, RgbaImage};
+
+use crate::error::Result;
+use crate::legend::Legend;
+use crate::types::{
+	Color,
+	Point,
+	ShapesLayout,
+};
+use crate::utils;
+
+/// Difference between the shapes of a layout and the image it was built from, in layout pixels.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Verification
+{
+	/// Pixels of a color not covered by any shape of the color.
+	pub missing: Vec<(Point, Color)>,
+	/// Pixels covered by a shape of the color although they are empty or of another color.
+	pub extra: Vec<(Point, Color)>,
+	/// Pixels covered by more than one box or point of the shapes.
+	pub double_covered: Vec<Point>,
+}
+
+impl Verification
+{
+	/// The shapes cover exactly the pixels of their colors.
+	pub fn is_exact(&self) -> bool {
+		self.missing.is_empty() && self.extra.is_empty() && self.double_covered.is_empty()
+	}
+}
+
+impl ShapesLayout
+{
+	/// Image of `width` × `height` pixels with every shape painted in its color over transparent pixels.
+	/// A layout pixel is painted as a block of `cell_size` image pixels.
+	pub fn render_to_image(&self, width: u32, height: u32) -> RgbaImage {
+		let cell_size = self.cell_size.unwrap_or(1);
+		let mut img = RgbaImage::new(width, height);
+
+		for (color, shapes) in &self.shapes {
+			let rgba = Rgba([color.r, color.g, color.b, color.a]);
+
+			for bbox in shapes.iter().flat_map(|shape| shape.get_pieces()) {
+				for y in bbox.min.y as u32 * cell_size .. ((bbox.max.y as u32 + 1) * cell_size).min(height) {
+					for x in bbox.min.x as u32 * cell_size .. ((bbox.max.x as u32 + 1) * cell_size).min(width) {
+						img.put_pixel(x, y, rgba);
+					}
+				}
+			}
+		}
+
+		img
+	}
+
+	/// Compares the shapes with the pixels of `img` the layout was built from with `legend`.
+	/// Colors of entity and connection layers aren't shapes and are expected to be left uncovered.
+	pub fn verify(&self, img: &RgbaImage, legend: &Legend) -> Result<Verification> {
+		let source = utils::get_layout_image(img, legend)?;
+		let (width, height) = (source.width() as usize, source.height() as usize);
+
+		let removed: HashSet<Color> = legend.layers.values()
+			.filter(|layer| layer.entity.is_some() || layer.connection)
+			.map(|layer| layer.color)
+			.collect();
+
+		let expected: Vec<Option<Color>> = (0 .. height)
+			.flat_map(|y| (0 .. width).map(move |x| (x, y)))
+			.map(|(x, y)| utils::get_pixel_color(&source, x as u32, y as u32, legend).filter(|color| !removed.contains(color)))
+			.collect();
+
+		let mut verification = Verification::default();
+		let mut counts = vec![0u32; width * height];
+		let mut covered = vec![false; width * height];
+
+		for (color, shapes) in &self.shapes {
+			for bbox in shapes.iter().flat_map(|shape| shape.get_pieces()) {
+				for y in bbox.min.y ..= bbox.max.y {
+					for x in bbox.min.x ..= bbox.max.x {
+						if x >= width || y >= height {
+							verification.extra.push((Point::new(x, y), *color));
+							continue;
+						}
+
+						let index = y * width + x;
+						counts[index] += 1;
+
+						if expected[index] == Some(*color) {
+							covered[index] = true;
+						} else {
+							verification.extra.push((Point::new(x, y), *color));
+						}
+					}
+				}
+			}
+		}
+
+		for (index, color) in expected.iter().enumerate() {
+			let point = Point::new(index % width, index / width);
+
+			if counts[index] > 1 {
+				verification.double_covered.push(point);
+			}
+
+			if let Some(color) = color {
+				if !covered[index] {
+					verification.missing.push((point, *color));
+				}
+			}
+		}
+
+		verification.extra.sort();
+		verification.extra.dedup();
+
+		Ok(verification)
+	}
+}
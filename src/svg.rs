@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use image::RgbaImage;
+
+use crate::error::Result;
+use crate::tiled::escape_xml;
+use crate::types::{
+	self,
+	BBox,
+	Color,
+	Point,
+	Shape,
+	ShapesLayout,
+};
+
+/// What the SVG debug rendering draws besides the source pixels, the shape pieces and labels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SvgOptions
+{
+	/// Size of a layout pixel in SVG units.
+	pub scale: u32,
+	/// Outer boundary of every shape.
+	pub outlines: bool,
+	/// Empty regions enclosed by complex shapes.
+	pub holes: bool,
+	/// Edges between touching shapes of the colors in `color_dependencies`.
+	pub dependencies: bool,
+}
+
+impl Default for SvgOptions
+{
+	fn default() -> Self {
+		Self { scale: 16, outlines: false, holes: false, dependencies: false }
+	}
+}
+
+impl SvgOptions
+{
+	/// Options with the overlays of a comma-separated list of `outlines`, `holes`, `dependencies` or `all`.
+	pub fn from_overlays(overlays: &str) -> Option<Self> {
+		let mut options = Self::default();
+
+		for overlay in overlays.split(',').map(str::trim) {
+			match overlay {
+				"outlines" => options.outlines = true,
+				"holes" => options.holes = true,
+				"dependencies" => options.dependencies = true,
+				"all" => {
+					options.outlines = true;
+					options.holes = true;
+					options.dependencies = true;
+				},
+				_ => return None,
+			}
+		}
+
+		Some(options)
+	}
+}
+
+impl ShapesLayout
+{
+	/// Debug picture of the layout: pixels of `sources`, the images the layout was built from after preprocessing
+	/// (see `get_layout_image`), with outlines of the shape boxes and circles on single points on top,
+	/// labeled with the shape index and color. Pixels left without outlines aren't covered by any shape.
+	/// A layered layout is drawn as a group per image layer, the first one at the bottom, with a source per image layer
+	/// in the same order.
+	pub fn to_svg_string(&self, sources: &[&RgbaImage], options: &SvgOptions) -> String {
+		let mut svg = get_svg_header(self.width, self.height, options);
+
+		if self.image_layers.is_empty() {
+			if let Some(source) = sources.first() {
+				write_svg_source(&mut svg, source, options);
+			}
+			write_svg_layout(&mut svg, self, &self.shapes, options);
+		}
+
+		for (index, layer) in self.image_layers.iter().enumerate() {
+			let _ = writeln!(svg, "<g id=\"{}\">", escape_xml(&layer.name));
+			if let Some(source) = sources.get(index) {
+				write_svg_source(&mut svg, source, options);
+			}
+			write_svg_layout(&mut svg, self, &layer.shapes, options);
+			svg.push_str("</g>\n");
+		}
+
+		svg.push_str("</svg>\n");
+		svg
+	}
+
+	pub fn write_svg<P: AsRef<Path>>(&self, path: P, sources: &[&RgbaImage], options: &SvgOptions) -> Result<()> {
+		std::fs::write(path, self.to_svg_string(sources, options))?;
+
+		Ok(())
+	}
+}
+
+fn get_svg_header(width: usize, height: usize, options: &SvgOptions) -> String {
+	let (width, height) = (width * options.scale as usize, height * options.scale as usize);
+
+	format!(
+		"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+		width, height, width, height,
+	)
+}
+
+/// Non-transparent pixels of the source image, a rect per run of equal pixels in a row.
+fn write_svg_source(svg: &mut String, source: &RgbaImage, options: &SvgOptions) {
+	let scale = options.scale as f64;
+
+	svg.push_str("<g class=\"source\">\n");
+
+	for y in 0 .. source.height() {
+		let mut x = 0;
+
+		while x < source.width() {
+			let pixel = source.get_pixel(x, y);
+			let start = x;
+
+			while x < source.width() && source.get_pixel(x, y) == pixel {
+				x += 1;
+			}
+
+			let [r, g, b, a] = pixel.0;
+			if a == 0 {
+				continue;
+			}
+
+			let bbox = BBox::new_xy(start as usize, y as usize, x as usize - 1, y as usize);
+			let (left, top, width, height) = get_rect(&bbox, scale);
+			let _ = write!(svg, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"", left, top, width, height, Color::new(r, g, b));
+
+			if a < 255 {
+				let _ = write!(svg, " fill-opacity=\"{}\"", a as f64 / 255.0);
+			}
+
+			svg.push_str("/>\n");
+		}
+	}
+
+	svg.push_str("</g>\n");
+}
+
+/// Shapes of the layout or of one of its image layers.
+fn write_svg_layout(svg: &mut String, layout: &ShapesLayout, shapes: &BTreeMap<Color, Vec<Shape>>, options: &SvgOptions) {
+	let scale = options.scale as f64;
+	let stroke_width = (scale / 8.0).max(1.0);
+
+	// pieces of the shapes over the source pixels, boxes are outlined inside their pixels
+	for (color, shapes) in shapes {
+		let contrast = get_contrast_color(color);
+
+		for shape in shapes {
+			let (bboxes, points) = get_pieces(shape);
+
+			for bbox in &bboxes {
+				let (x, y, width, height) = get_rect(bbox, scale);
+				let _ = writeln!(
+					svg,
+					"<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"/>",
+					x + stroke_width / 2.0, y + stroke_width / 2.0, width - stroke_width, height - stroke_width, contrast, stroke_width,
+				);
+			}
+
+			for point in &points {
+				let (x, y) = get_center(point, scale);
+				let _ = writeln!(svg, "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>", x, y, scale / 4.0, contrast);
+			}
+		}
+	}
+
+	if options.outlines {
+		for shape in shapes.values().flatten() {
+			for outline in shape.get_outlines() {
+				let _ = writeln!(
+					svg,
+					"<path d=\"{}\" fill=\"none\" stroke=\"#0080FF\" stroke-width=\"{}\"/>",
+					get_path(&outline.boundary, scale), stroke_width,
+				);
+			}
+		}
+	}
+
+	if options.holes {
+		for hole in shapes.values().flatten().flat_map(Shape::get_holes) {
+			let _ = writeln!(
+				svg,
+				"<path d=\"{}\" fill=\"#FF00FF\" fill-opacity=\"0.3\" stroke=\"#FF00FF\" stroke-width=\"{}\" stroke-dasharray=\"{}\"/>",
+				get_path(&hole.outline, scale), stroke_width, stroke_width * 2.0,
+			);
+		}
+	}
+
+	if options.dependencies {
+		for (shape, other) in get_touching_shapes(layout, shapes) {
+			let (x1, y1) = get_bbox_center(&shapes[&shape.0][shape.1].get_bbox(), scale);
+			let (x2, y2) = get_bbox_center(&shapes[&other.0][other.1].get_bbox(), scale);
+			let _ = writeln!(
+				svg,
+				"<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#FF8000\" stroke-width=\"{}\"/>",
+				x1, y1, x2, y2, stroke_width,
+			);
+		}
+	}
+
+	for (color, shapes) in shapes {
+		for (index, shape) in shapes.iter().enumerate() {
+			let point = shape.get_first_point();
+			let _ = writeln!(
+				svg,
+				"<text x=\"{}\" y=\"{}\" font-family=\"monospace\" font-size=\"{}\" fill=\"#000000\" stroke=\"#FFFFFF\" stroke-width=\"{}\" paint-order=\"stroke\">{} {}</text>",
+				point.x as f64 * scale, (point.y as f64 + 0.75) * scale, scale * 0.75, stroke_width, index, color,
+			);
+		}
+	}
+}
+
+/// Boxes and single points a shape is split into.
+fn get_pieces(shape: &Shape) -> (Vec<BBox>, Vec<Point>) {
+	match shape {
+		Shape::Pixel(point) => (Vec::new(), vec![*point]),
+		Shape::Box(bbox) => (vec![*bbox], Vec::new()),
+		Shape::Complex(geom) => (geom.get_bboxes().iter().cloned().collect(), geom.get_points().iter().cloned().collect()),
+	}
+}
+
+/// Pairs of shapes touching by a side whose colors are dependencies of each other.
+fn get_touching_shapes(layout: &ShapesLayout, all_shapes: &BTreeMap<Color, Vec<Shape>>) -> BTreeSet<((Color, usize), (Color, usize))> {
+	let mut pairs = BTreeSet::new();
+
+	for (color, shapes) in all_shapes {
+		for (index, shape) in shapes.iter().enumerate() {
+			let pieces = shape.get_pieces();
+
+			for other in types::get_shapes_near(all_shapes, &shape.get_bbox()) {
+				if other <= (*color, index) || other.0 == *color || !layout.are_adjacent(color, &other.0) {
+					continue;
+				}
+
+				let other_pieces = all_shapes[&other.0][other.1].get_pieces();
+				if pieces.iter().any(|piece| other_pieces.iter().any(|other_piece| are_touching(piece, other_piece))) {
+					pairs.insert(((*color, index), other));
+				}
+			}
+		}
+	}
+
+	pairs
+}
+
+/// Boxes touch by a side, not only by a corner.
+fn are_touching(bbox: &BBox, other: &BBox) -> bool {
+	let overlap_x = bbox.min.x <= other.max.x && other.min.x <= bbox.max.x;
+	let overlap_y = bbox.min.y <= other.max.y && other.min.y <= bbox.max.y;
+
+	(overlap_y && (bbox.max.x + 1 == other.min.x || other.max.x + 1 == bbox.min.x)) ||
+	(overlap_x && (bbox.max.y + 1 == other.min.y || other.max.y + 1 == bbox.min.y))
+}
+
+fn get_rect(bbox: &BBox, scale: f64) -> (f64, f64, f64, f64) {
+	(bbox.min.x as f64 * scale, bbox.min.y as f64 * scale, bbox.get_width() as f64 * scale, bbox.get_height() as f64 * scale)
+}
+
+fn get_center(point: &Point, scale: f64) -> (f64, f64) {
+	((point.x as f64 + 0.5) * scale, (point.y as f64 + 0.5) * scale)
+}
+
+fn get_bbox_center(bbox: &BBox, scale: f64) -> (f64, f64) {
+	((bbox.min.x + bbox.max.x + 1) as f64 * scale / 2.0, (bbox.min.y + bbox.max.y + 1) as f64 * scale / 2.0)
+}
+
+/// Closed path through pixel corners.
+fn get_path(corners: &[Point], scale: f64) -> String {
+	let mut path = String::new();
+
+	for (i, corner) in corners.iter().enumerate() {
+		let _ = write!(path, "{}{} {} ", if i == 0 { 'M' } else { 'L' }, corner.x as f64 * scale, corner.y as f64 * scale);
+	}
+
+	path.push('Z');
+	path
+}
+
+/// Black or white, whichever is more visible over `color`.
+fn get_contrast_color(color: &Color) -> &'static str {
+	let luminance = 0.299 * color.r as f64 + 0.587 * color.g as f64 + 0.114 * color.b as f64;
+
+	if luminance > 128.0 { "#000000" } else { "#FFFFFF" }
+}
@@ -1,5 +1,20 @@
+use crate::cells::*;
+use crate::connections::*;
+use crate::contour::*;
+use crate::decomposition::*;
+use crate::entities::*;
+use crate::error::Error;
+use crate::json::JSON_VERSION;
+use crate::layered::*;
+use crate::ldtk::*;
+use crate::legend::*;
+use crate::palette::*;
+use crate::svg::*;
+use crate::tiled::*;
 use crate::types::*;
+use crate::labeling::{self, Connectivity};
 use crate::utils;
+use crate::world::*;
 
 use std::iter::FromIterator;
 
@@ -424,7 +439,7 @@ fn test_complex_geometry_bbox() {
 
 	for (points, min, max) in &data
 	{
-		let set: HashSet<Point> = HashSet::from_iter(points.into_iter().cloned());
+		let set: HashSet<Point> = HashSet::from_iter(points.iter().cloned());
 		let shape = ComplexGeometry::new(set.clone());
 
 		let bbox = shape.get_outer_bbox();
@@ -465,7 +480,7 @@ fn test_complex_geometry_try_get_as_bbox() {
 
 	for (points, sample_bbox) in &data
 	{
-		let set: HashSet<Point> = HashSet::from_iter(points.into_iter().cloned());
+		let set: HashSet<Point> = HashSet::from_iter(points.iter().cloned());
 		let shape = ComplexGeometry::new(set.clone());
 
 		let bbox = shape.try_get_as_bbox();
@@ -473,3 +488,1575 @@ fn test_complex_geometry_try_get_as_bbox() {
 		assert_eq!(bbox, *sample_bbox);
 	}
 }
+
+#[test]
+fn test_ron_round_trip() {
+	for img_path in &["assets/black_sample.png", "assets/color_sample.png"] {
+		let layout = utils::get_shapes_layout(img_path).unwrap();
+
+		let ron = layout.to_ron_string().unwrap();
+		let loaded = ShapesLayout::from_ron(&ron).unwrap();
+
+		assert_eq!(loaded, layout);
+	}
+}
+
+#[test]
+fn test_ron_write_read() {
+	let layout = utils::get_shapes_layout("assets/black_sample.png").unwrap();
+
+	let ron_path = std::env::temp_dir().join("layouter_test_ron_write_read.ron");
+	layout.write_ron(&ron_path).unwrap();
+
+	let loaded = ShapesLayout::read_ron(&ron_path).unwrap();
+	std::fs::remove_file(&ron_path).unwrap();
+
+	assert_eq!(loaded, layout);
+}
+
+#[test]
+fn test_legend_parse() {
+	let legend = Legend::read("assets/color_sample.legend.ron").unwrap();
+
+	assert!(!legend.strict);
+	assert_eq!(legend.layers.len(), 5);
+
+	let water = legend.layers.get("water").unwrap();
+	assert_eq!(water.color, Color::new(43, 147, 132));
+	assert_eq!(water.properties.get("solid"), Some(&Property::Bool(false)));
+	assert_eq!(water.properties.get("friction"), Some(&Property::Float(0.2)));
+
+	let spikes = legend.layers.get("spikes").unwrap();
+	assert_eq!(spikes.color, Color::new(255, 0, 0));
+	assert_eq!(spikes.properties.get("damage"), Some(&Property::Int(100)));
+	assert_eq!(spikes.properties.get("trigger"), Some(&Property::String("hurt".to_string())));
+
+	assert_eq!(legend.get_layer_by_color(&Color::new(217, 31, 35)).unwrap().0, "lava");
+
+	assert!(Legend::from_ron(r##"(layers: {"a": (color: "#12345")})"##).is_err());
+	assert!(matches!(
+		Legend::from_ron(r##"(layers: {"a": (color: "#123456"), "b": (color: "#123456")})"##),
+		Err(Error::DuplicateColor { .. })
+	));
+}
+
+#[test]
+fn test_legend_apply() {
+	let mut legend = Legend::read("assets/color_sample.legend.ron").unwrap();
+
+	let mut layout = utils::get_shapes_layout("assets/color_sample.png").unwrap();
+	layout.apply_legend(&legend).unwrap();
+
+	assert_eq!(layout.warnings.len(), layout.shapes.len() - legend.layers.len());
+	assert_eq!(layout.warnings[0], Warning::UnknownColor { color: Color::new(255, 255, 0), point: Point::new(2, 1) });
+
+	assert_eq!(layout.get_layer("water").unwrap().color, Color::new(43, 147, 132));
+	assert_eq!(layout.get_layer_shapes("water").unwrap().len(), 2);
+	assert_eq!(layout.get_layer_shapes("spikes").unwrap().len(), 2);
+	assert!(layout.get_layer_shapes("clouds").is_none());
+
+	// every color is reachable by a name, unknown ones by their hex values
+	let water = &layout.layers["water"];
+	assert_eq!(layout.get_layer_shapes("water"), Some(layout.shapes[&water.color].as_slice()));
+	assert_eq!(water.properties["friction"], Property::Float(0.2));
+
+	let unknown = &layout.layers["#FFFF00"];
+	assert_eq!(unknown.color, Color::new(255, 255, 0));
+	assert!(unknown.properties.is_empty());
+	assert_eq!(layout.get_layer_shapes("#FFFF00"), Some(layout.shapes[&unknown.color].as_slice()));
+	assert_eq!(layout.layers.len(), layout.shapes.len());
+
+	// shapes are written once, under their color
+	let ron = layout.to_ron_string().unwrap();
+	let loaded = ShapesLayout::from_ron(&ron).unwrap();
+	assert_eq!(loaded.layers, layout.layers);
+	assert_eq!(ron.matches("Box(").count(), layout.shapes.values().flatten().filter(|shape| matches!(shape, Shape::Box(_))).count());
+
+	let world = Transform { scale: 2.0, ..Default::default() }.apply(&layout);
+	assert_eq!(world.get_layer_shapes("water"), Some(world.shapes[&water.color].as_slice()));
+	assert_eq!(world.get_layer_shapes("snow"), None);
+
+	// legend layers absent in the image are kept without shapes
+	let mut sparse = legend.clone();
+	sparse.layers.extend(Legend::from_ron(r##"(layers: {"clouds": (color: "#010203")})"##).unwrap().layers);
+	let mut layout = utils::get_shapes_layout("assets/color_sample.png").unwrap();
+	layout.apply_legend(&sparse).unwrap();
+	assert_eq!(layout.get_layer_shapes("clouds"), Some(&[][..]));
+
+	// hex names are left for unknown colors
+	assert!(matches!(Legend::from_ron(r##"(layers: {"#FF0000": (color: "#00FF00")})"##), Err(Error::InvalidLegend(_))));
+
+	legend.strict = true;
+
+	let mut layout = utils::get_shapes_layout("assets/color_sample.png").unwrap();
+	match layout.apply_legend(&legend) {
+		Err(Error::UnknownColor { color, point }) => {
+			assert_eq!(color, Color::new(255, 255, 0));
+			assert_eq!(point, Point::new(2, 1));
+		},
+		other => panic!("unexpected result {:?}", other),
+	}
+}
+
+#[test]
+fn test_color_dependencies() {
+	let layout = utils::get_shapes_layout("assets/color_sample.png").unwrap();
+
+	let black = Color::BLACK;
+	let wall = Color::new(25, 25, 25);
+	let floor = Color::new(50, 50, 50);
+	let pit = Color::new(75, 75, 75);
+	let water = Color::new(43, 147, 132);
+	let lava = Color::new(217, 31, 35);
+
+	// adjacency is symmetric
+	for (color, neighbours) in &layout.color_dependencies {
+		assert!(!neighbours.contains(color));
+
+		for neighbour in neighbours {
+			assert!(layout.are_adjacent(neighbour, color));
+		}
+	}
+
+	assert!(layout.are_adjacent(&water, &lava));
+	assert!(layout.are_adjacent(&wall, &floor));
+	assert!(layout.are_adjacent(&floor, &pit));
+	assert!(!layout.are_adjacent(&wall, &pit));
+	assert!(!layout.are_adjacent(&water, &black));
+
+	assert!(layout.is_enclosed_by(&wall, &black));
+	assert!(layout.is_enclosed_by(&pit, &black));
+	assert!(layout.is_enclosed_by(&pit, &wall));
+	assert!(layout.is_enclosed_by(&pit, &floor));
+	assert!(!layout.is_enclosed_by(&floor, &wall));
+	assert!(!layout.is_enclosed_by(&black, &wall));
+	assert!(!layout.is_enclosed_by(&water, &lava));
+
+	// nothing can be enclosed by the only color of the image
+	let layout = utils::get_shapes_layout("assets/black_sample.png").unwrap();
+	assert!(layout.color_enclosures.is_empty());
+	assert!(layout.color_dependencies.is_empty());
+}
+
+#[test]
+fn test_labeling_merges_components() {
+	// U-shaped component is seen as two components until the bottom row joins them
+	let rows = [
+		"#.#.#",
+		"#.#.#",
+		"###.#",
+		"#...#",
+		"#####",
+	];
+
+	let components = labeling::label_components(5, 5, |x, y| if rows[y].as_bytes()[x] == b'#' { Some(()) } else { None }, |_| Connectivity::Four);
+
+	assert_eq!(components.get_count(), 1);
+	assert_eq!(components.get_first_point(0), Point::new(0, 0));
+	assert_eq!(components.get_label(3, 0), labeling::NO_LABEL);
+	assert_eq!(components.get_stats(), vec![(BBox::new_xy(0, 0, 4, 4), 17)]);
+
+	// equal keys only are connected
+	let components = labeling::label_components(5, 5, |x, y| Some(rows[y].as_bytes()[x]), |_| Connectivity::Four);
+
+	assert_eq!(components.get_count(), 3);
+	assert_eq!(components.get_label(1, 1), 1);
+	assert_eq!(components.get_label(3, 0), 2);
+	assert_eq!(components.get_label(2, 3), 2);
+}
+
+#[test]
+fn test_large_regions() {
+	// a region this large used to overflow the stack of the recursive flood fill
+	let img = image::RgbaImage::from_pixel(2000, 2000, image::Rgba([0, 0, 0, 255]));
+
+	let layout = utils::get_shapes_layout_by_image(&img, &Legend::default()).unwrap();
+	assert_eq!(layout.shapes.get(&Color::BLACK).unwrap(), &vec![Shape::Box(BBox::new_xy(0, 0, 1999, 1999))]);
+
+	// long one pixel wide snake
+	let mut img = image::RgbaImage::from_pixel(60, 60, image::Rgba([255, 255, 255, 255]));
+	for y in (0 .. 60).step_by(2) {
+		for x in 0 .. 60 {
+			img.put_pixel(x, y, image::Rgba([0, 0, 0, 255]));
+		}
+
+		let x = if y % 4 == 0 { 59 } else { 0 };
+		if y + 2 < 60 {
+			img.put_pixel(x, y + 1, image::Rgba([0, 0, 0, 255]));
+		}
+	}
+
+	let layout = utils::get_shapes_layout_by_image(&img, &Legend::default()).unwrap();
+	let shapes = layout.shapes.get(&Color::BLACK).unwrap();
+	assert_eq!(shapes.len(), 1);
+
+	if let Shape::Complex(geom) = &shapes[0] {
+		assert_eq!(*geom.get_outer_bbox(), BBox::new_xy(0, 0, 59, 58));
+		assert!(geom.get_bboxes().contains(&BBox::new_xy(59, 0, 59, 2)));
+		assert!(geom.get_bboxes().contains(&BBox::new_xy(0, 2, 0, 4)));
+	} else {
+		panic!("expected complex shape");
+	}
+}
+
+#[test]
+fn test_bbox_beyond_9999() {
+	let points: HashSet<Point> = HashSet::from_iter(vec![Point::new(20_000, 15_001), Point::new(20_002, 15_000), Point::new(20_001, 15_003)]);
+	assert_eq!(utils::calc_bbox_by_points(&points), BBox::new_xy(20_000, 15_000, 20_002, 15_003));
+
+	// Г shape far to the right of a wide image
+	let mut img = image::RgbaImage::from_pixel(10_050, 4, image::Rgba([255, 255, 255, 255]));
+	for (x, y) in &[(10_020, 0), (10_021, 0), (10_020, 1), (10_020, 2), (10_040, 3)] {
+		img.put_pixel(*x, *y, image::Rgba([0, 0, 0, 255]));
+	}
+
+	let layout = utils::get_shapes_layout_by_image(&img, &Legend::default()).unwrap();
+	let shapes = layout.shapes.get(&Color::BLACK).unwrap();
+	assert_eq!(shapes.len(), 2);
+
+	for shape in shapes {
+		match shape {
+			Shape::Complex(geom) => {
+				assert_eq!(*geom.get_outer_bbox(), BBox::new_xy(10_020, 0, 10_021, 2));
+				assert_eq!(geom.get_bboxes().len(), 1);
+				assert!(geom.get_bboxes().contains(&BBox::new_xy(10_020, 0, 10_020, 2)));
+				assert_eq!(geom.get_points().len(), 1);
+				assert!(geom.get_points().contains(&Point::new(10_021, 0)));
+			},
+			Shape::Pixel(point) => assert_eq!(*point, Point::new(10_040, 3)),
+			Shape::Box(_) => panic!("unexpected box"),
+		}
+	}
+}
+
+#[test]
+fn test_oversized_image() {
+	assert!(utils::check_image_dimensions(10_050, 4).is_ok());
+	assert!(matches!(utils::check_image_dimensions(0, 0), Err(Error::EmptyImage)));
+	assert!(matches!(utils::check_image_dimensions(16, 0), Err(Error::EmptyImage)));
+
+	assert!(matches!(utils::check_image_dimensions(70_000, 70_000), Err(Error::ImageTooLarge { .. })));
+	assert!(matches!(utils::check_image_dimensions(u32::MAX, 2), Err(Error::ImageTooLarge { .. })));
+}
+
+#[test]
+fn test_deterministic_output() {
+	for img_path in &["assets/black_sample.png", "assets/color_sample.png"] {
+		let first = utils::get_shapes_layout(img_path).unwrap().to_ron_string().unwrap();
+		let second = utils::get_shapes_layout(img_path).unwrap().to_ron_string().unwrap();
+
+		assert_eq!(first.as_bytes(), second.as_bytes());
+	}
+
+	let layout = utils::get_shapes_layout("assets/color_sample.png").unwrap();
+
+	let colors: Vec<&Color> = layout.shapes.keys().collect();
+	assert!(colors.windows(2).all(|pair| pair[0] < pair[1]));
+
+	for shapes in layout.shapes.values() {
+		let first_points: Vec<Point> = shapes.iter().map(Shape::get_first_point).collect();
+		assert!(first_points.windows(2).all(|pair| pair[0] < pair[1]));
+	}
+
+	let black = layout.shapes.get(&Color::BLACK).unwrap();
+	assert_eq!(black[0].get_first_point(), Point::new(15, 2));
+	assert_eq!(black[1], Shape::Box(BBox::new_xy(6, 5, 7, 5)));
+	assert_eq!(black[2], Shape::Pixel(Point::new(1, 11)));
+
+	if let Shape::Complex(geom) = &black[0] {
+		let bboxes: Vec<&BBox> = geom.get_bboxes().iter().collect();
+		assert_eq!(*bboxes[0], BBox::new_xy(15, 2, 15, 15));
+		assert!(bboxes.windows(2).all(|pair| pair[0] < pair[1]));
+	} else {
+		panic!("expected complex shape");
+	}
+
+	assert!(Point::new(5, 0) < Point::new(0, 1));
+}
+
+fn points_from_rows(rows: &[&str]) -> HashSet<Point> {
+	rows.iter().enumerate()
+		.flat_map(|(y, row)| row.chars().enumerate().filter(|(_, c)| *c == '#').map(move |(x, _)| Point::new(x, y)))
+		.collect()
+}
+
+/// Splits `points` with `decomposition`, checks the pieces cover them exactly once and returns the pieces count.
+fn check_decomposition(points: &HashSet<Point>, decomposition: Decomposition) -> usize {
+	let outer_bbox = utils::calc_bbox_by_points(points);
+	let (bboxes, single_points) = decomposition.get_strategy().split(points, &outer_bbox).into_parts();
+
+	let mut covered = HashSet::new();
+	for bbox in &bboxes {
+		for x in bbox.min.x ..= bbox.max.x {
+			for y in bbox.min.y ..= bbox.max.y {
+				assert!(covered.insert(Point::new(x, y)), "{:?}: ({}, {}) is covered twice", decomposition, x, y);
+			}
+		}
+	}
+
+	for point in &single_points {
+		assert!(covered.insert(*point), "{:?}: {:?} is covered twice", decomposition, point);
+	}
+
+	assert_eq!(&covered, points, "{:?}", decomposition);
+
+	bboxes.len() + single_points.len()
+}
+
+#[test]
+fn test_decomposition_strategies() {
+	let h_shape = points_from_rows(&[
+		"#.#",
+		"###",
+		"#.#",
+	]);
+
+	assert_eq!(check_decomposition(&h_shape, Decomposition::TallestColumn), 3);
+	assert_eq!(check_decomposition(&h_shape, Decomposition::RowMerge), 5);
+	assert_eq!(check_decomposition(&h_shape, Decomposition::MinimumRectangles), 3);
+
+	let stairs = points_from_rows(&[
+		"#...",
+		"##..",
+		"###.",
+		"####",
+	]);
+
+	assert_eq!(check_decomposition(&stairs, Decomposition::RowMerge), 4);
+	assert_eq!(check_decomposition(&stairs, Decomposition::MinimumRectangles), 4);
+
+	let ring = points_from_rows(&[
+		"#####",
+		"#...#",
+		"#...#",
+		"#####",
+	]);
+
+	assert_eq!(check_decomposition(&ring, Decomposition::MinimumRectangles), 4);
+
+	let cross = points_from_rows(&[
+		".##..",
+		"#####",
+		"#####",
+		".##..",
+	]);
+
+	assert_eq!(check_decomposition(&cross, Decomposition::TallestColumn), 3);
+	assert_eq!(check_decomposition(&cross, Decomposition::RowMerge), 3);
+	assert_eq!(check_decomposition(&cross, Decomposition::MinimumRectangles), 3);
+}
+
+/// Fewest rectangles partitioning `points`, by exhaustive search: the first free point in row order
+/// is the top-left corner of its rectangle, every rectangle fitting there is tried, largest first.
+fn brute_force_min_rectangles(points: &HashSet<Point>) -> usize {
+	fn search(free: &mut BTreeSet<(usize, usize)>, count: usize, best: &mut usize) {
+		let (y, x) = match free.iter().next() {
+			Some(first) => *first,
+			None => {
+				*best = count;
+				return;
+			},
+		};
+
+		if count + 1 >= *best {
+			return;
+		}
+
+		let mut max_x = x;
+		while free.contains(&(y, max_x + 1)) {
+			max_x += 1;
+		}
+
+		for right in (x ..= max_x).rev() {
+			let mut bottom = y;
+			while (x ..= right).all(|cx| free.contains(&(bottom + 1, cx))) {
+				bottom += 1;
+			}
+
+			for max_y in (y ..= bottom).rev() {
+				let cells: Vec<(usize, usize)> = (y ..= max_y).flat_map(|cy| (x ..= right).map(move |cx| (cy, cx))).collect();
+				cells.iter().for_each(|cell| { free.remove(cell); });
+				search(free, count + 1, best);
+				free.extend(cells);
+			}
+		}
+	}
+
+	let mut free = points.iter().map(|point| (point.y, point.x)).collect();
+	let mut best = points.len();
+	search(&mut free, 0, &mut best);
+	best
+}
+
+#[test]
+fn test_minimum_rectangles_is_minimal() {
+	// diagonal neighbours are one shape with 8-connectivity, the corner between them isn't reflex
+	let pinched = points_from_rows(&[
+		"###..",
+		"###..",
+		"##.##",
+		"..###",
+		"..###",
+	]);
+	let pinched_ring = points_from_rows(&[
+		"####.",
+		"#..#.",
+		"#..##",
+		"####.#",
+		"...###",
+	]);
+	let comb = points_from_rows(&[
+		"#.#.#",
+		"#####",
+		".#.#.",
+		"#####",
+	]);
+
+	for points in &[pinched, pinched_ring, comb] {
+		assert_eq!(check_decomposition(points, Decomposition::MinimumRectangles), brute_force_min_rectangles(points), "{:?}", points);
+	}
+
+	// deterministic blobs from a linear congruential generator
+	let mut seed: u32 = 12345;
+	let mut next = || {
+		seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+		(seed >> 16) % 100
+	};
+
+	for _ in 0 .. 300 {
+		let density = 40 + next() % 50;
+		let points: HashSet<Point> = (0 .. 5).flat_map(|y| (0 .. 5).map(move |x| Point::new(x, y)))
+			.filter(|_| next() < density)
+			.collect();
+
+		if points.is_empty() {
+			continue;
+		}
+
+		let minimum = check_decomposition(&points, Decomposition::MinimumRectangles);
+		assert_eq!(minimum, brute_force_min_rectangles(&points), "{:?}", points);
+
+		let tallest = check_decomposition(&points, Decomposition::TallestColumn);
+		let rows = check_decomposition(&points, Decomposition::RowMerge);
+		assert!(minimum <= tallest && minimum <= rows, "{} > min({}, {}) for {:?}", minimum, tallest, rows, points);
+	}
+}
+
+#[test]
+fn test_decomposition_per_layer() {
+	let legend = Legend::from_ron(r##"(
+		decomposition: RowMerge,
+		layers: {
+			"wall": (color: "#000000", decomposition: Some(MinimumRectangles)),
+		},
+	)"##).unwrap();
+
+	assert_eq!(legend.get_decomposition(&Color::BLACK), Decomposition::MinimumRectangles);
+	assert_eq!(legend.get_decomposition(&Color::new(1, 2, 3)), Decomposition::RowMerge);
+	assert_eq!(Decomposition::from_name("row-merge"), Some(Decomposition::RowMerge));
+	assert_eq!(Decomposition::from_name("MinimumRectangles"), Some(Decomposition::MinimumRectangles));
+	assert_eq!(Decomposition::from_name("best"), None);
+
+	let img = image::open("assets/black_sample.png").unwrap().into_rgba();
+	let layout = utils::get_shapes_layout_by_image(&img, &legend).unwrap();
+	let default_layout = utils::get_shapes_layout_by_image(&img, &Legend::default()).unwrap();
+
+	let pieces_count = |layout: &ShapesLayout| -> usize {
+		layout.shapes[&Color::BLACK].iter()
+			.map(|shape| match shape {
+				Shape::Complex(geom) => geom.get_bboxes().len() + geom.get_points().len(),
+				_ => 1,
+			})
+			.sum()
+	};
+
+	assert!(pieces_count(&layout) < pieces_count(&default_layout));
+	assert_eq!(layout.shapes[&Color::BLACK].len(), default_layout.shapes[&Color::BLACK].len());
+}
+
+/// Shoelace area of a loop, positive for clockwise loops with y pointing down.
+fn loop_area(vertices: &[Point]) -> isize {
+	let doubled: isize = vertices.iter().zip(vertices.iter().cycle().skip(1))
+		.map(|(a, b)| a.x as isize * b.y as isize - b.x as isize * a.y as isize)
+		.sum();
+
+	doubled / 2
+}
+
+#[test]
+fn test_outlines() {
+	let ring = points_from_rows(&[
+		"###",
+		"#.#",
+		"###",
+	]);
+
+	let outlines = trace_outlines(&ring, Connectivity::Four);
+	assert_eq!(outlines.len(), 1);
+	assert_eq!(outlines[0].boundary, vec![Point::new(0, 0), Point::new(3, 0), Point::new(3, 3), Point::new(0, 3)]);
+	assert_eq!(outlines[0].holes, vec![vec![Point::new(1, 1), Point::new(1, 2), Point::new(2, 2), Point::new(2, 1)]]);
+
+	// the empty pixel touches the outside by a corner, so it's not a hole
+	let notch = points_from_rows(&[
+		"###",
+		"#.#",
+		"##.",
+	]);
+
+	let outlines = trace_outlines(&notch, Connectivity::Four);
+	assert_eq!(outlines.len(), 1);
+	assert!(outlines[0].holes.is_empty());
+	assert_eq!(outlines[0].boundary, vec![
+		Point::new(0, 0), Point::new(3, 0), Point::new(3, 2), Point::new(2, 2), Point::new(2, 1),
+		Point::new(1, 1), Point::new(1, 2), Point::new(2, 2), Point::new(2, 3), Point::new(0, 3),
+	]);
+
+	let diagonal = points_from_rows(&[
+		"#.",
+		".#",
+	]);
+
+	let outlines = trace_outlines(&diagonal, Connectivity::Four);
+	assert_eq!(outlines.len(), 2);
+	assert_eq!(outlines[1], Outline::from_bbox(&BBox::new_xy(1, 1, 1, 1)));
+
+	assert_eq!(Shape::Box(BBox::new_xy(2, 3, 4, 3)).get_outlines(), vec![Outline {
+		boundary: vec![Point::new(2, 3), Point::new(5, 3), Point::new(5, 4), Point::new(2, 4)],
+		holes: Vec::new(),
+	}]);
+}
+
+#[test]
+fn test_outlines_area() {
+	for img_path in &["assets/black_sample.png", "assets/color_sample.png"] {
+		let layout = utils::get_shapes_layout(img_path).unwrap();
+
+		for shape in layout.shapes.values().flatten() {
+			let outlines = shape.get_outlines();
+			assert_eq!(outlines.len(), 1);
+
+			let outline = &outlines[0];
+			assert_eq!(outline.boundary[0], shape.get_first_point());
+
+			let area = loop_area(&outline.boundary) + outline.holes.iter().map(|hole| loop_area(hole)).sum::<isize>();
+			assert_eq!(area as usize, shape.get_covered_points().len());
+			assert!(outline.holes.iter().all(|hole| loop_area(hole) < 0));
+		}
+	}
+}
+
+#[test]
+fn test_holes() {
+	let rooms = points_from_rows(&[
+		"#########",
+		"#..#....#",
+		"#..#.##.#",
+		"#..#....#",
+		"#########",
+	]);
+
+	let shape = Shape::Complex(ComplexGeometry::new(rooms.clone()));
+	let holes = shape.get_holes();
+
+	assert_eq!(holes.len(), 2);
+	assert_eq!(holes[0].bbox, BBox::new_xy(1, 1, 2, 3));
+	assert_eq!(holes[0].get_points_count(), 6);
+	assert_eq!(holes[0].outline, vec![Point::new(1, 1), Point::new(1, 4), Point::new(3, 4), Point::new(3, 1)]);
+
+	// the island is a separate region lying in the second room
+	assert_eq!(holes[1].bbox, BBox::new_xy(4, 1, 7, 3));
+	assert_eq!(holes[1].get_points_count(), 12);
+	assert_eq!(shape.get_outlines().len(), 2);
+
+	assert!(shape.encloses(&Point::new(2, 2)));
+	assert!(shape.encloses(&Point::new(4, 2)));
+	assert!(shape.encloses(&Point::new(5, 2)));
+	assert!(!shape.encloses(&Point::new(3, 2)));
+	assert!(!shape.encloses(&Point::new(10, 2)));
+
+	let layout = utils::get_shapes_layout("assets/black_sample.png").unwrap();
+	for shape in layout.shapes.values().flatten() {
+		let holes_count: usize = shape.get_outlines().iter().map(|outline| outline.holes.len()).sum();
+		assert_eq!(shape.get_holes().len(), holes_count);
+	}
+
+	assert!(Shape::Box(BBox::new_xy(0, 0, 5, 5)).get_holes().is_empty());
+
+	let ron = ShapesLayout {
+		shapes: BTreeMap::from_iter(vec![(Color::BLACK, vec![shape])]),
+		..Default::default()
+	}.to_ron_string().unwrap();
+	assert_eq!(ShapesLayout::from_ron(&ron).unwrap().shapes[&Color::BLACK][0].get_holes().len(), 2);
+}
+
+#[test]
+fn test_eight_connectivity() {
+	let rows = [
+		"#...#",
+		".#.#.",
+		"..#..",
+		".#.#.",
+	];
+
+	let key = |x: usize, y: usize| if rows[y].as_bytes()[x] == b'#' { Some(()) } else { None };
+	assert_eq!(labeling::label_components(5, 4, key, |_| Connectivity::Four).get_count(), 7);
+	assert_eq!(labeling::label_components(5, 4, key, |_| Connectivity::Eight).get_count(), 1);
+
+	let points = points_from_rows(&rows);
+	let strategy = Decomposition::default().get_strategy();
+	assert_eq!(utils::get_shapes_by_points(&points, strategy, Connectivity::Four).len(), 7);
+
+	let shapes = utils::get_shapes_by_points(&points, strategy, Connectivity::Eight);
+	assert_eq!(shapes.len(), 1);
+	assert_eq!(shapes[0].get_covered_points(), points);
+
+	let outlines = shapes[0].get_outlines();
+	assert_eq!(outlines.len(), 1);
+	assert_eq!(loop_area(&outlines[0].boundary), 7);
+
+	// empty pixels of a diagonal ring don't leak through its corners
+	let diamond = points_from_rows(&[
+		".#.",
+		"#.#",
+		".#.",
+	]);
+
+	assert!(trace_outlines(&diamond, Connectivity::Four).iter().all(|outline| outline.holes.is_empty()));
+
+	let geometry = ComplexGeometry::new_with_options(diamond, strategy, Connectivity::Eight);
+	assert_eq!(geometry.get_holes().len(), 1);
+	assert_eq!(geometry.get_holes()[0].bbox, BBox::new_xy(1, 1, 1, 1));
+}
+
+#[test]
+fn test_connectivity_per_layer() {
+	let mut img = image::RgbaImage::from_pixel(6, 6, image::Rgba([255, 255, 255, 255]));
+	for i in 0 .. 6 {
+		img.put_pixel(i, i, image::Rgba([0, 128, 0, 255]));
+		img.put_pixel(5 - i, i, image::Rgba([0, 0, 0, 255]));
+	}
+
+	let legend = Legend::from_ron(r##"(
+		layers: {
+			"vines": (color: "#008000", connectivity: Some(Eight)),
+			"wall": (color: "#000000"),
+		},
+	)"##).unwrap();
+
+	let layout = utils::get_shapes_layout_by_image(&img, &legend).unwrap();
+	assert_eq!(layout.shapes[&Color::new(0, 128, 0)].len(), 1);
+	assert_eq!(layout.shapes[&Color::BLACK].len(), 6);
+
+	let mut legend = legend;
+	legend.connectivity = Connectivity::Eight;
+	let layout = utils::get_shapes_layout_by_image(&img, &legend).unwrap();
+	assert_eq!(layout.shapes[&Color::BLACK].len(), 1);
+
+	if let Shape::Complex(geom) = &layout.shapes[&Color::BLACK][0] {
+		assert_eq!(geom.get_connectivity(), Connectivity::Eight);
+		let ron = ShapesLayout::from_ron(&layout.to_ron_string().unwrap()).unwrap();
+		assert_eq!(ron, layout);
+	} else {
+		panic!("expected complex shape");
+	}
+
+	assert_eq!(Connectivity::from_name("8"), Some(Connectivity::Eight));
+	assert_eq!(Connectivity::from_name("6"), None);
+}
+
+#[test]
+fn test_background_and_alpha() {
+	let magenta = image::Rgba([255, 0, 255, 255]);
+	let mut img = image::RgbaImage::from_pixel(4, 2, magenta);
+	img.put_pixel(0, 0, image::Rgba([255, 255, 255, 255]));
+	img.put_pixel(1, 0, image::Rgba([10, 20, 30, 255]));
+	img.put_pixel(2, 0, image::Rgba([10, 20, 30, 200]));
+	img.put_pixel(3, 0, image::Rgba([10, 20, 30, 100]));
+
+	let dark = Color::new(10, 20, 30);
+
+	let layout = utils::get_shapes_layout_by_image(&img, &Legend::default()).unwrap();
+	assert_eq!(layout.shapes.keys().cloned().collect::<Vec<_>>(), vec![dark, Color::new(255, 0, 255)]);
+	assert_eq!(layout.shapes[&dark], vec![Shape::Pixel(Point::new(1, 0))]);
+
+	let legend = Legend::from_ron(r##"(
+		background: ["#FF00FF", "#FFFFFF"],
+		alpha_threshold: 128,
+	)"##).unwrap();
+
+	let layout = utils::get_shapes_layout_by_image(&img, &legend).unwrap();
+	assert_eq!(layout.shapes.keys().cloned().collect::<Vec<_>>(), vec![dark]);
+	assert_eq!(layout.shapes[&dark], vec![Shape::Box(BBox::new_xy(1, 0, 2, 0))]);
+
+	let legend = Legend { keep_alpha: true, alpha_threshold: 0, ..legend };
+	let layout = utils::get_shapes_layout_by_image(&img, &legend).unwrap();
+
+	let translucent = Color::new_rgba(10, 20, 30, 200);
+	assert_eq!(layout.shapes.len(), 3);
+	assert_eq!(layout.shapes[&translucent], vec![Shape::Pixel(Point::new(2, 0))]);
+	assert!(layout.are_adjacent(&dark, &translucent));
+
+	assert_eq!(translucent.to_hex(), "#0A141EC8");
+	assert_eq!(Color::from_hex("#0A141EC8"), Some(translucent));
+	assert_eq!(Color::from_hex("0A141E"), Some(dark));
+	assert_eq!(ShapesLayout::from_ron(&layout.to_ron_string().unwrap()).unwrap(), layout);
+
+	// alpha of background colors is ignored like alpha of the pixels
+	let legend = Legend::from_ron(r##"(background: ["#FF00FF80", "#FFFFFF00"])"##).unwrap();
+	let layout = utils::get_shapes_layout_by_image(&img, &legend).unwrap();
+	assert_eq!(layout.shapes.keys().cloned().collect::<Vec<_>>(), vec![dark]);
+	assert_eq!(legend.get_pixel_color(&[255, 0, 255, 255]), None);
+	assert_eq!(legend.get_pixel_color(&[255, 255, 255, 255]), None);
+
+	let legend = Legend::from_ron(r##"(layers: {"glass": (color: (r: 10, g: 20, b: 30, a: 200))})"##).unwrap();
+	assert_eq!(legend.layers["glass"].color, translucent);
+	assert_eq!(legend.background, vec![Color::WHITE]);
+	assert_eq!(legend.alpha_threshold, 255);
+}
+
+/// Two blocks of noisy red and blue on white with a soft edge and a green pixel far from both.
+fn get_noisy_image() -> image::RgbaImage {
+	image::RgbaImage::from_fn(12, 4, |x, y| {
+		let noise = ((x * 7 + y * 13) % 5) as u8;
+
+		match x {
+			0 ..= 3 => image::Rgba([250 - noise, noise, noise, 255]),
+			4 => image::Rgba([253, 245, 245, 255]),
+			5 ..= 8 if (x, y) == (6, 1) => image::Rgba([0, 200, 0, 255]),
+			5 ..= 8 => image::Rgba([noise, noise, 250 - noise, 255]),
+			_ => image::Rgba([255, 255, 255, 255]),
+		}
+	})
+}
+
+#[test]
+fn test_palette_snapping() {
+	let img = get_noisy_image();
+	assert!(utils::get_shapes_layout_by_image(&img, &Legend::default()).unwrap().shapes.len() > 4);
+
+	let legend = Legend::from_ron(r##"(
+		snapping: Some((tolerance: 20.0)),
+		layers: {
+			"lava": (color: "#FF0000"),
+			"water": (color: "#0000FF"),
+		},
+	)"##).unwrap();
+
+	let layout = utils::get_shapes_layout_by_image(&img, &legend).unwrap();
+	let (red, blue, green) = (Color::new(255, 0, 0), Color::new(0, 0, 255), Color::new(0, 200, 0));
+
+	assert_eq!(layout.shapes.keys().cloned().collect::<Vec<_>>(), vec![blue, green, red]);
+	assert_eq!(layout.shapes[&red], vec![Shape::Box(BBox::new_xy(0, 0, 3, 3))]);
+
+	// the soft edge is closer to white than to red
+	let report = layout.snap_report.as_ref().unwrap();
+	assert_eq!(report.snapped_count, 16 + 15 + 4);
+	assert_eq!(report.out_of_tolerance, BTreeMap::from_iter(vec![(green, vec![Point::new(6, 1)])]));
+	assert_eq!(layout.warnings, vec![Warning::OutOfTolerance { color: green, point: Point::new(6, 1), count: 1 }]);
+
+	// in Lab the green pixel is even further
+	let lab = Snapping { color_space: ColorSpace::Lab, tolerance: 10.0, ..Default::default() };
+	let mut snapped = img.clone();
+	let report = lab.apply(&mut snapped, &legend);
+	assert_eq!(report.out_of_tolerance.len(), 1);
+	assert_eq!(snapped.get_pixel(1, 1).0, [255, 0, 0, 255]);
+	assert!(ColorSpace::Lab.get_distance(&Color::WHITE, &Color::BLACK) > 99.0);
+
+	let kmeans = Legend { snapping: Some(Snapping { palette: PaletteSource::KMeans(4), ..Default::default() }), ..Legend::default() };
+	let layout = utils::get_shapes_layout_by_image(&img, &kmeans).unwrap();
+	let report = layout.snap_report.as_ref().unwrap();
+
+	// red, blue, green, the soft edge and white background
+	assert_eq!(report.palette.len(), 5);
+	assert!(report.out_of_tolerance.is_empty());
+	assert_eq!(layout.shapes.len(), 4);
+	assert!(layout.shapes.values().all(|shapes| shapes.len() == 1));
+
+	assert_eq!(get_kmeans_palette(&img, &Legend::default(), 4, ColorSpace::Rgb), report.palette[.. 4].to_vec());
+	assert_eq!(PaletteSource::from_name("8"), Some(PaletteSource::KMeans(8)));
+	assert_eq!(PaletteSource::from_name("0"), None);
+}
+
+/// Every pixel of `img` as a `size` × `size` block.
+fn upscale(img: &image::RgbaImage, size: u32) -> image::RgbaImage {
+	image::RgbaImage::from_fn(img.width() * size, img.height() * size, |x, y| *img.get_pixel(x / size, y / size))
+}
+
+#[test]
+fn test_cell_size() {
+	let img = image::open("assets/color_sample.png").unwrap().into_rgba();
+	let layout = utils::get_shapes_layout_by_image(&img, &Legend::default()).unwrap();
+	assert_eq!(layout.cell_size, None);
+
+	let mut blocks = upscale(&img, 8);
+	// stray pixels of other colors don't change majority of their cells
+	blocks.put_pixel(3, 3, image::Rgba([1, 2, 3, 255]));
+	blocks.put_pixel(19, 60, image::Rgba([255, 255, 255, 255]));
+
+	assert_eq!(detect_cell_size(&blocks, &Legend::default()), 8);
+	assert_eq!(detect_cell_size(&img, &Legend::default()), 1);
+
+	for cell_size in &[CellSize::Fixed(8), CellSize::Auto] {
+		let legend = Legend { cell_size: *cell_size, ..Legend::default() };
+		let cells_layout = utils::get_shapes_layout_by_image(&blocks, &legend).unwrap();
+
+		assert_eq!(cells_layout.shapes, layout.shapes);
+		assert_eq!(cells_layout.color_dependencies, layout.color_dependencies);
+		assert_eq!(cells_layout.cell_size, Some(8));
+		assert_eq!(cells_layout.warnings.len(), 2);
+		assert!(matches!(cells_layout.warnings[0], Warning::MixedCell { cell: Point { x: 0, y: 0 }, colors_count: 2, .. }));
+	}
+
+	// a tie is won by the color met first
+	let mut tie = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 255, 255, 255]));
+	tie.put_pixel(1, 0, image::Rgba([0, 0, 0, 255]));
+	tie.put_pixel(1, 1, image::Rgba([0, 0, 0, 255]));
+	let mut warnings = Vec::new();
+
+	let cells = downsample(&tie, 2, &Legend::default(), &mut warnings);
+	assert_eq!(cells.dimensions(), (1, 1));
+	assert_eq!(cells.get_pixel(0, 0).0, [255, 255, 255, 255]);
+	assert_eq!(warnings, vec![Warning::MixedCell { cell: Point::new(0, 0), colors_count: 2, color: None }]);
+
+	// partial cells at the right and bottom borders
+	let cells = downsample(&upscale(&tie, 3), 4, &Legend::default(), &mut warnings);
+	assert_eq!(cells.dimensions(), (2, 2));
+	assert_eq!(cells.get_pixel(1, 1).0, [0, 0, 0, 255]);
+
+	assert_eq!(CellSize::from_name("16"), Some(CellSize::Fixed(16)));
+	assert_eq!(CellSize::from_name("0"), None);
+}
+
+#[test]
+fn test_world_transform() {
+	let layout = utils::get_shapes_layout("assets/color_sample.png").unwrap();
+	assert_eq!((layout.width, layout.height), (36, 18));
+
+	let identity = Transform::default().apply(&layout);
+	assert_eq!(identity.shapes[&Color::new(200, 200, 200)], vec![WorldShape::Rect(WorldRect {
+		min: WorldPoint::new(2.0, 7.0),
+		max: WorldPoint::new(4.0, 8.0),
+	})]);
+
+	let transform = Transform {
+		scale: 0.5,
+		origin: WorldPoint::new(-10.0, 1.0),
+		flip_y: true,
+		anchor: PixelAnchor::Center,
+	};
+
+	let world = transform.apply(&layout);
+	assert_eq!((world.width, world.height), (18.0, 9.0));
+
+	// the box spans rows 7 ..= 7, that is 10 ..= 11 counting from the bottom
+	assert_eq!(world.shapes[&Color::new(200, 200, 200)], vec![WorldShape::Rect(WorldRect {
+		min: WorldPoint::new(-9.0, 6.0),
+		max: WorldPoint::new(-8.0, 6.5),
+	})]);
+
+	assert_eq!(world.shapes[&Color::new(0, 0, 255)][0], WorldShape::Point(WorldPoint::new(-4.75, 9.25)));
+	let corner = Transform { anchor: PixelAnchor::Corner, ..transform };
+	assert_eq!(corner.get_point(&Point::new(10, 1), layout.height), WorldPoint::new(-5.0, 9.0));
+
+	// complex shapes cover the same area
+	for (shape, world_shape) in layout.shapes.values().flatten().zip(world.shapes.values().flatten()) {
+		if let WorldShape::Complex { rects, .. } = world_shape {
+			let area: f64 = rects.iter().map(|rect| rect.get_width() * rect.get_height()).sum();
+			assert_eq!(area, shape.get_covered_points().len() as f64 * 0.25);
+		}
+	}
+
+	// flipped outlines go counter-clockwise
+	let polygon = transform.get_polygon(&Outline::from_bbox(&BBox::new_xy(0, 17, 0, 17)), layout.height);
+	assert_eq!(polygon.boundary, vec![
+		WorldPoint::new(-10.0, 1.5), WorldPoint::new(-9.5, 1.5), WorldPoint::new(-9.5, 1.0), WorldPoint::new(-10.0, 1.0),
+	]);
+
+	assert_eq!(WorldLayout::from_ron(&world.to_ron_string().unwrap()).unwrap(), world);
+
+	// a ring keeps its hole, flipping y reverses both loops
+	let mut img = image::RgbaImage::from_pixel(3, 3, image::Rgba([0, 0, 0, 255]));
+	img.put_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+	let ring = utils::get_shapes_layout_by_rgba_image(&img, &Legend::default()).unwrap();
+
+	// twice the signed area, positive for clockwise loops in a y-down space
+	let get_area = |points: &[WorldPoint]| -> f64 {
+		points.iter().zip(points.iter().cycle().skip(1)).map(|(a, b)| a.x * b.y - b.x * a.y).sum()
+	};
+
+	let get_polygons = |transform: &Transform| -> Vec<WorldPolygon> {
+		match &transform.apply(&ring).shapes[&Color::BLACK][0] {
+			WorldShape::Complex { polygons, .. } => polygons.clone(),
+			other => panic!("unexpected {:?}", other),
+		}
+	};
+
+	let polygons = get_polygons(&Transform::default());
+	assert_eq!(polygons.len(), 1);
+	assert_eq!(polygons[0].holes.len(), 1);
+	assert_eq!(get_area(&polygons[0].boundary), 18.0);
+	assert_eq!(get_area(&polygons[0].holes[0]), -2.0);
+
+	let flipped = get_polygons(&Transform { flip_y: true, ..Default::default() });
+	assert_eq!(flipped[0].holes.len(), 1);
+	assert_eq!(get_area(&flipped[0].boundary), -18.0);
+	assert_eq!(get_area(&flipped[0].holes[0]), 2.0);
+	assert_eq!(flipped[0].holes[0].iter().map(|point| point.y).fold(f64::MAX, f64::min), 1.0);
+
+	let layered = get_layered_layout_by_images(&[("walls", &img)], &Legend::default()).unwrap();
+	let world = Transform { flip_y: true, ..Default::default() }.apply(&layered);
+	assert!(matches!(&world.get_image_layer("walls").unwrap().shapes[&Color::BLACK][0], WorldShape::Complex { polygons, .. } if *polygons == flipped));
+	assert_eq!(WorldLayout::from_json(&world.to_json_string().unwrap()).unwrap(), world);
+}
+
+#[test]
+fn test_layered_layout() {
+	let mut walls = image::RgbaImage::from_pixel(8, 6, image::Rgba([255, 255, 255, 255]));
+	let mut triggers = walls.clone();
+
+	for x in 0 .. 8 {
+		walls.put_pixel(x, 5, image::Rgba([0, 0, 0, 255]));
+	}
+
+	// the trigger overlaps the floor, they are kept apart by their layers
+	for y in 4 .. 6 {
+		triggers.put_pixel(3, y, image::Rgba([255, 0, 0, 255]));
+	}
+
+	let legend = Legend::from_ron(r##"(
+		layers: {
+			"floor": (color: "#000000"),
+			"trigger": (color: "#FF0000"),
+		},
+	)"##).unwrap();
+	let layered = get_layered_layout_by_images(&[("walls", &walls), ("triggers", &triggers)], &legend).unwrap();
+	assert_eq!((layered.width, layered.height), (8, 6));
+	assert_eq!(layered.image_layers.iter().map(|layer| layer.name.as_str()).collect::<Vec<_>>(), vec!["walls", "triggers"]);
+	assert!(layered.shapes.is_empty());
+
+	let walls_layer = layered.get_image_layer("walls").unwrap();
+	assert_eq!(walls_layer.shapes.keys().collect::<Vec<_>>(), vec![&Color::BLACK]);
+	assert_eq!(walls_layer.shapes[&Color::BLACK], vec![Shape::Box(BBox::new_xy(0, 5, 7, 5))]);
+
+	let triggers_layer = layered.get_image_layer("triggers").unwrap();
+	assert_eq!(triggers_layer.shapes[&Color::new(255, 0, 0)], vec![Shape::Box(BBox::new_xy(3, 4, 3, 5))]);
+	assert_eq!(layered.get_image_layer_shapes("triggers", "trigger"), Some(&[Shape::Box(BBox::new_xy(3, 4, 3, 5))][..]));
+	assert_eq!(layered.get_image_layer_shapes("walls", "trigger"), Some(&[][..]));
+	assert!(layered.get_image_layer("decoration").is_none());
+
+	// color relations are joined from all images
+	assert!(!layered.are_adjacent(&Color::new(255, 0, 0), &Color::BLACK));
+	triggers.put_pixel(4, 4, image::Rgba([0, 0, 0, 255]));
+	let joined = get_layered_layout_by_images(&[("walls", &walls), ("triggers", &triggers)], &legend).unwrap();
+	assert!(joined.are_adjacent(&Color::new(255, 0, 0), &Color::BLACK));
+
+	assert_eq!(ShapesLayout::from_ron(&layered.to_ron_string().unwrap()).unwrap(), layered);
+
+	let world = Transform { scale: 2.0, ..Default::default() }.apply(&layered);
+	assert_eq!(world.height, 12.0);
+	assert_eq!(world.get_image_layer("walls").unwrap().shapes[&Color::BLACK].len(), 1);
+	assert_eq!(WorldLayout::from_ron(&world.to_ron_string().unwrap()).unwrap(), world);
+
+	match get_layered_layout_by_images(&[("walls", &walls), ("walls", &triggers)], &legend) {
+		Err(Error::DuplicateLayer(name)) => assert_eq!(name, "walls"),
+		other => panic!("unexpected {:?}", other),
+	}
+
+	let small = image::RgbaImage::new(4, 6);
+	match get_layered_layout_by_images(&[("walls", &walls), ("small", &small)], &legend) {
+		Err(Error::SizeMismatch { layer, size, expected }) => {
+			assert_eq!(layer, "small");
+			assert_eq!(size, (4, 6));
+			assert_eq!(expected, ("walls".to_string(), (8, 6)));
+		},
+		other => panic!("unexpected {:?}", other),
+	}
+
+	// an empty layer gets the cell size detected on the others
+	let mut collisions = image::RgbaImage::from_pixel(64, 64, image::Rgba([255, 255, 255, 255]));
+	for (x, y, pixel) in collisions.enumerate_pixels_mut() {
+		if (x / 16 + y / 16) % 2 == 0 && y >= 16 {
+			*pixel = image::Rgba([0, 0, 0, 255]);
+		}
+	}
+	let markers = image::RgbaImage::from_pixel(64, 64, image::Rgba([255, 255, 255, 255]));
+	assert_eq!(detect_cell_size(&markers, &legend), 1);
+
+	let legend = Legend { cell_size: CellSize::Auto, ..Legend::default() };
+	for images in &[[("markers", &markers), ("collisions", &collisions)], [("collisions", &collisions), ("markers", &markers)]] {
+		let layered = get_layered_layout_by_images(images, &legend).unwrap();
+		assert_eq!((layered.width, layered.height, layered.cell_size), (4, 4, Some(16)));
+
+		assert_eq!(layered.get_image_layer("collisions").unwrap().shapes[&Color::BLACK].len(), 6);
+	}
+
+	// noisy cells are detected after snapping, as for a single image
+	let noisy = image::RgbaImage::from_fn(128, 128, |x, y| {
+		let noise = ((x * 7 + y * 13) % 11) as u8;
+		if (x / 16 + y / 16) % 2 == 0 {
+			image::Rgba([noise, 5 + noise, 10 - noise, 255])
+		} else {
+			image::Rgba([250 + noise / 2, 255 - noise, 245 + noise, 255])
+		}
+	});
+	let legend = Legend::from_ron(r##"(
+		cell_size: Auto,
+		snapping: Some((tolerance: 20.0)),
+		layers: { "walls": (color: "#000000") },
+	)"##).unwrap();
+
+	let single = utils::get_shapes_layout_by_image(&noisy, &legend).unwrap();
+	assert_eq!((single.width, single.cell_size), (8, Some(16)));
+
+	let layered = get_layered_layout_by_images(&[("walls", &noisy)], &legend).unwrap();
+	assert_eq!((layered.width, layered.cell_size), (8, Some(16)));
+}
+
+#[test]
+fn test_in_memory_images() {
+	let legend = Legend::default();
+	let path = "assets/color_sample.png";
+	let expected = utils::get_shapes_layout_with_legend(path, &legend).unwrap();
+
+	let img = image::open(path).unwrap();
+	assert_eq!(utils::get_shapes_layout_by_dynamic_image(&img, &legend).unwrap(), expected);
+	assert_eq!(utils::get_shapes_layout_by_rgba_image(&img.to_rgba(), &legend).unwrap(), expected);
+
+	// images without alpha are converted
+	let mut rgb = image::RgbImage::from_pixel(4, 3, image::Rgb([255, 255, 255]));
+	rgb.put_pixel(1, 1, image::Rgb([255, 0, 0]));
+	let rgba = image::DynamicImage::ImageRgb8(rgb.clone()).to_rgba();
+	assert_eq!(
+		utils::get_shapes_layout_by_dynamic_image(&image::DynamicImage::ImageRgb8(rgb), &legend).unwrap(),
+		utils::get_shapes_layout_by_rgba_image(&rgba, &legend).unwrap(),
+	);
+
+	let bytes = std::fs::read(path).unwrap();
+	assert_eq!(utils::get_shapes_layout_by_bytes(&bytes, None, &legend).unwrap(), expected);
+	assert_eq!(utils::get_shapes_layout_by_bytes(&bytes, Some(image::ImageFormat::Png), &legend).unwrap(), expected);
+	assert!(utils::get_shapes_layout_by_bytes(&bytes, Some(image::ImageFormat::Bmp), &legend).is_err());
+	assert!(utils::get_shapes_layout_by_bytes(&bytes[.. 100], None, &legend).is_err());
+
+	let reader = std::io::Cursor::new(&bytes);
+	assert_eq!(utils::get_shapes_layout_by_reader(reader, None, &legend).unwrap(), expected);
+	let file = std::fs::File::open(path).unwrap();
+	assert_eq!(utils::get_shapes_layout_by_reader(file, Some(image::ImageFormat::Png), &legend).unwrap(), expected);
+}
+
+#[test]
+fn test_errors() {
+	let legend = Legend::default();
+
+	match utils::get_shapes_layout_with_legend("assets/missing.png", &legend) {
+		Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+		other => panic!("unexpected {:?}", other),
+	}
+
+	assert!(matches!(utils::get_shapes_layout_by_bytes(b"not an image", Some(image::ImageFormat::Png), &legend), Err(Error::Decode(_))));
+	assert!(matches!(utils::get_shapes_layout_by_rgba_image(&image::RgbaImage::new(0, 0), &legend), Err(Error::EmptyImage)));
+
+	assert!(matches!(Legend::from_ron("(strict: maybe)"), Err(Error::Parse(_))));
+	assert!(matches!(ShapesLayout::from_ron("(shapes: 1)"), Err(Error::Parse(_))));
+	assert!(matches!(Legend::read("assets/missing.legend.ron"), Err(Error::Io(_))));
+
+	for ron in &["(cell_size: Fixed(0))", "(snapping: Some((palette: KMeans(0))))", "(snapping: Some((tolerance: -1.0)))", "(transform: Some((scale: 0.0)))"] {
+		assert!(matches!(Legend::from_ron(ron), Err(Error::InvalidLegend(_))), "{}", ron);
+	}
+
+	// settings are checked before processing as well
+	let legend = Legend { cell_size: CellSize::Fixed(0), ..Default::default() };
+	assert!(matches!(utils::get_shapes_layout_by_rgba_image(&image::RgbaImage::new(2, 2), &legend), Err(Error::InvalidLegend(_))));
+
+	let error = Error::UnknownColor { color: Color::new(255, 255, 0), point: Point::new(2, 1) };
+	assert_eq!(error.to_string(), "color #FFFF00 at (2, 1) is not in the legend");
+	assert_eq!(Error::ImageTooLarge { width: 70_000, height: 70_000 }.to_string(), "image of 70000×70000 pixels is too large");
+
+	let layout = utils::get_shapes_layout("assets/black_sample.png").unwrap();
+	assert!(matches!(layout.write_ron("assets/missing/layout.ron"), Err(Error::Io(_))));
+}
+
+#[test]
+fn test_entities() {
+	let legend = Legend::from_ron(r##"(
+		strict: true,
+		layers: {
+			"wall": (color: "#000000"),
+			"player": (color: "#0000FF", entity: Some((min: 1, max: Some(1)))),
+			"coin": (color: "#FFFF00", entity: Some(())),
+		},
+	)"##).unwrap();
+	assert_eq!(legend.layers["player"].entity, Some(EntityRule::exactly(1)));
+	assert_eq!(legend.layers["coin"].entity, Some(EntityRule::default()));
+
+	let mut img = image::RgbaImage::from_pixel(8, 4, image::Rgba([255, 255, 255, 255]));
+	for x in 0 .. 8 {
+		img.put_pixel(x, 3, image::Rgba([0, 0, 0, 255]));
+	}
+	img.put_pixel(1, 2, image::Rgba([0, 0, 255, 255]));
+	img.put_pixel(4, 1, image::Rgba([255, 255, 0, 255]));
+	img.put_pixel(6, 0, image::Rgba([255, 255, 0, 255]));
+	img.put_pixel(6, 1, image::Rgba([255, 255, 0, 255]));
+
+	let layout = utils::get_shapes_layout_by_rgba_image(&img, &legend).unwrap();
+	assert_eq!(layout.shapes.keys().collect::<Vec<_>>(), vec![&Color::BLACK]);
+	assert_eq!(layout.entities, vec![
+		Entity { kind: "coin".to_string(), position: Point::new(6, 0), bbox: BBox::new_xy(6, 0, 6, 1) },
+		Entity { kind: "coin".to_string(), position: Point::new(4, 1), bbox: BBox::new_xy(4, 1, 4, 1) },
+		Entity { kind: "player".to_string(), position: Point::new(1, 2), bbox: BBox::new_xy(1, 2, 1, 2) },
+	]);
+	assert_eq!(layout.get_entities("coin").count(), 2);
+	assert_eq!(layout.get_entities("enemy").count(), 0);
+	assert_eq!(ShapesLayout::from_ron(&layout.to_ron_string().unwrap()).unwrap().entities, layout.entities);
+
+	let world = Transform { anchor: PixelAnchor::Center, ..Default::default() }.apply(&layout);
+	assert_eq!(world.entities[2], WorldEntity {
+		kind: "player".to_string(),
+		position: WorldPoint::new(1.5, 2.5),
+		bounds: WorldRect { min: WorldPoint::new(1.0, 2.0), max: WorldPoint::new(2.0, 3.0) },
+	});
+
+	img.put_pixel(5, 2, image::Rgba([0, 0, 255, 255]));
+	match utils::get_shapes_layout_by_rgba_image(&img, &legend) {
+		Err(error @ Error::EntityCount { .. }) => {
+			assert_eq!(error.to_string(), "expected exactly 1 `player` entities, found 2 at (1, 2), (5, 2)");
+		},
+		other => panic!("unexpected {:?}", other),
+	}
+
+	img.put_pixel(1, 2, image::Rgba([255, 255, 255, 255]));
+	img.put_pixel(5, 2, image::Rgba([255, 255, 255, 255]));
+	match utils::get_shapes_layout_by_rgba_image(&img, &legend) {
+		Err(Error::EntityCount { kind, points, .. }) => {
+			assert_eq!(kind, "player");
+			assert!(points.is_empty());
+		},
+		other => panic!("unexpected {:?}", other),
+	}
+
+	assert!(matches!(
+		Legend::from_ron(r##"(layers: {"boss": (color: "#FF0000", entity: Some((min: 2, max: Some(1))))})"##),
+		Err(Error::InvalidLegend(_))
+	));
+}
+
+#[test]
+fn test_connections() {
+	let legend = Legend::from_ron(r##"(
+		layers: {
+			"door": (color: "#000000"),
+			"switch": (color: "#FF0000", entity: Some(())),
+			"teleporter": (color: "#0000FF", entity: Some(())),
+			"wire": (color: "#00FF00", connection: true),
+		},
+	)"##).unwrap();
+
+	let mut img = image::RgbaImage::from_pixel(10, 8, image::Rgba([255, 255, 255, 255]));
+	let mut put = |x, y, rgb: [u8; 3]| img.put_pixel(x, y, image::Rgba([rgb[0], rgb[1], rgb[2], 255]));
+
+	for y in 0 .. 4 {
+		put(8, y, [0, 0, 0]);
+	}
+	put(1, 1, [255, 0, 0]);
+	put(1, 4, [0, 0, 255]);
+	put(8, 5, [0, 0, 255]);
+
+	// switch to door, teleporter to teleporter and a wire leading nowhere
+	for x in 2 .. 8 {
+		put(x, 1, [0, 255, 0]);
+		put(x, 4, [0, 255, 0]);
+	}
+	put(7, 5, [0, 255, 0]);
+	put(3, 7, [0, 255, 0]);
+	put(4, 7, [0, 255, 0]);
+
+	let layout = utils::get_shapes_layout_by_rgba_image(&img, &legend).unwrap();
+	assert_eq!(layout.shapes.keys().collect::<Vec<_>>(), vec![&Color::BLACK]);
+	assert_eq!(layout.entities.len(), 3);
+
+	assert_eq!(layout.connections, vec![
+		Connection { source: ShapeId::Entity(0), target: ShapeId::Shape(Color::BLACK, 0) },
+		Connection { source: ShapeId::Entity(1), target: ShapeId::Entity(2) },
+	]);
+	assert_eq!(layout.get_connected(ShapeId::Shape(Color::BLACK, 0)), vec![ShapeId::Entity(0)]);
+	assert_eq!(layout.get_shape(&Color::BLACK, 0), Some(&Shape::Box(BBox::new_xy(8, 0, 8, 3))));
+	assert_eq!(layout.warnings, vec![Warning::DanglingConnection { point: Point::new(3, 7), shapes_count: 0 }]);
+
+	let loaded = ShapesLayout::from_ron(&layout.to_ron_string().unwrap()).unwrap();
+	assert_eq!(loaded.connections, layout.connections);
+	assert_eq!(Transform::default().apply(&layout).connections, layout.connections);
+
+	// entities of image layers are appended, so connections of the later layers refer to them by the shifted index
+	let layered = get_layered_layout_by_images(&[("front", &img), ("back", &img)], &legend).unwrap();
+	assert_eq!(layered.entities.len(), 6);
+	assert_eq!(layered.image_layers[0].connections, layout.connections);
+	assert_eq!(layered.image_layers[1].connections, vec![
+		Connection { source: ShapeId::Entity(3), target: ShapeId::Shape(Color::BLACK, 0) },
+		Connection { source: ShapeId::Entity(4), target: ShapeId::Entity(5) },
+	]);
+	assert_eq!(layered.image_layers[1].get_shape(&Color::BLACK, 0), layout.get_shape(&Color::BLACK, 0));
+
+	assert!(matches!(
+		Legend::from_ron(r##"(layers: {"wire": (color: "#00FF00", entity: Some(()), connection: true)})"##),
+		Err(Error::InvalidLegend(_))
+	));
+}
+
+#[test]
+fn test_json_round_trip() {
+	let legend = Legend::read("assets/color_sample.legend.ron").unwrap();
+	let mut layout = utils::get_shapes_layout_with_legend("assets/color_sample.png", &legend).unwrap();
+	layout.warnings.clear();
+
+	let json = layout.to_json_string().unwrap();
+	assert_eq!(ShapesLayout::from_json(&json).unwrap(), layout);
+
+	let document: serde_json::Value = serde_json::from_str(&json).unwrap();
+	assert_eq!(document["version"], JSON_VERSION);
+	assert_eq!(document["layout"]["shapes"]["#0000FF"][0], serde_json::json!({"Pixel": {"x": 10, "y": 1}}));
+	assert_eq!(document["layout"]["layers"]["lava"]["properties"]["damage"], 10);
+
+	let world = Transform { scale: 0.5, ..Default::default() }.apply(&layout);
+	assert_eq!(WorldLayout::from_json(&world.to_json_string().unwrap()).unwrap(), world);
+
+	let img = image::open("assets/black_sample.png").unwrap().to_rgba();
+	let layered = get_layered_layout_by_images(&[("walls", &img)], &Legend::default()).unwrap();
+	assert_eq!(ShapesLayout::from_json(&layered.to_json_string().unwrap()).unwrap(), layered);
+
+	// optional fields may be missing
+	let minimal = ShapesLayout::from_json(r##"{"version": 1, "layout": {"shapes": {"#FF0000": [{"Box": {"min": {"x": 0, "y": 0}, "max": {"x": 1, "y": 1}}}]}, "color_dependencies": {}}}"##).unwrap();
+	assert_eq!(minimal.shapes[&Color::new(255, 0, 0)], vec![Shape::Box(BBox::new_xy(0, 0, 1, 1))]);
+
+	assert!(matches!(ShapesLayout::from_json(r#"{"version": 2, "layout": {}}"#), Err(Error::UnsupportedVersion(2))));
+	assert!(matches!(ShapesLayout::from_json(r#"{"layout": {}}"#), Err(Error::Json(_))));
+	assert!(matches!(ShapesLayout::from_json(r#"{"version": 1, "layout": {"shapes": 1}}"#), Err(Error::Json(_))));
+
+	// the documented schema is of the written version
+	let schema: serde_json::Value = serde_json::from_str(&std::fs::read_to_string("docs/layout.schema.json").unwrap()).unwrap();
+	assert_eq!(schema["properties"]["version"]["const"], JSON_VERSION);
+}
+
+#[test]
+fn test_tiled_map() {
+	let legend = Legend::read("assets/color_sample.legend.ron").unwrap();
+	let layout = utils::get_shapes_layout_with_legend("assets/color_sample.png", &legend).unwrap();
+	let map = TiledMap::from_layout(&layout, TiledComplex::Polygon);
+
+	assert_eq!((map.width, map.height, map.tile_size), (layout.width, layout.height, 1));
+	assert_eq!(map.layers.len(), layout.shapes.len());
+
+	let objects_count: usize = map.layers.iter()
+		.map(|layer| match layer {
+			TiledLayer::Objects { objects, .. } => objects.len(),
+			TiledLayer::Group { .. } => 0,
+		})
+		.sum();
+	assert_eq!(map.next_object_id as usize, objects_count + 1);
+
+	let (name, properties, objects) = map.layers.iter()
+		.find_map(|layer| match layer {
+			TiledLayer::Objects { name, color, properties, objects, .. } if *color == Color::new(0xD9, 0x1F, 0x23) => Some((name, properties, objects)),
+			_ => None,
+		})
+		.unwrap();
+	assert_eq!(name, "lava");
+	assert_eq!(properties["damage"], Property::Int(10));
+	assert_eq!(objects.len(), layout.shapes[&Color::new(0xD9, 0x1F, 0x23)].len());
+
+	// pixels are points at their centers, polygons start at their first corner
+	let pixel = Shape::Pixel(Point::new(3, 4));
+	let boxes = Shape::Box(BBox::new_xy(1, 2, 3, 2));
+	let corner = Shape::Complex(ComplexGeometry::new(HashSet::from_iter(vec![Point::new(0, 0), Point::new(1, 0), Point::new(0, 1)])));
+	let mut layout = ShapesLayout { width: 4, height: 5, cell_size: Some(2), ..Default::default() };
+	layout.shapes.insert(Color::new(255, 0, 0), vec![boxes, pixel, corner]);
+
+	let map = TiledMap::from_layout(&layout, TiledComplex::Polygon);
+	assert_eq!(map.tile_size, 2);
+	let objects = match &map.layers[0] {
+		TiledLayer::Objects { name, objects, .. } => {
+			assert_eq!(name, "#FF0000");
+			objects
+		},
+		TiledLayer::Group { .. } => panic!("not an object layer"),
+	};
+	assert_eq!((objects[0].x, objects[0].y, &objects[0].kind), (2.0, 4.0, &TiledObjectKind::Rect { width: 6.0, height: 2.0 }));
+	assert_eq!((objects[1].x, objects[1].y, &objects[1].kind), (7.0, 9.0, &TiledObjectKind::Point));
+	assert_eq!((objects[2].x, objects[2].y), (0.0, 0.0));
+	assert_eq!(objects[2].kind, TiledObjectKind::Polygon(vec![(0.0, 0.0), (4.0, 0.0), (4.0, 2.0), (2.0, 2.0), (2.0, 4.0), (0.0, 4.0)]));
+
+	let tmx = map.to_tmx_string();
+	assert!(tmx.contains("width=\"4\" height=\"5\" tilewidth=\"2\" tileheight=\"2\""));
+	assert!(tmx.contains("<objectgroup id=\"1\" name=\"#FF0000\" color=\"#ff0000\">"));
+	assert!(tmx.contains("<point/>"));
+	assert!(tmx.contains("<polygon points=\"0,0 4,0 4,2 2,2 2,4 0,4\"/>"));
+
+	// complex shapes as rectangles sharing the shape index
+	let map = TiledMap::from_layout(&layout, TiledComplex::Rects);
+	let objects = match &map.layers[0] {
+		TiledLayer::Objects { objects, .. } => objects,
+		TiledLayer::Group { .. } => panic!("not an object layer"),
+	};
+	assert_eq!(objects.len(), 4);
+	assert!(objects[2 ..].iter().all(|object| object.properties["shape"] == Property::Int(2)));
+
+	let tmj: serde_json::Value = serde_json::from_str(&map.to_tmj_string().unwrap()).unwrap();
+	assert_eq!((tmj["width"].as_u64(), tmj["tilewidth"].as_u64()), (Some(4), Some(2)));
+	assert_eq!(tmj["layers"][0]["objects"][2]["properties"][0], serde_json::json!({"name": "shape", "type": "int", "value": 2}));
+	assert_eq!(tmj["layers"][0]["objects"][1]["point"], true);
+
+	let img = image::open("assets/black_sample.png").unwrap().to_rgba();
+	let layered = get_layered_layout_by_images(&[("walls", &img), ("triggers", &img)], &Legend::default()).unwrap();
+	let map = TiledMap::from_layout(&layered, TiledComplex::Polygon);
+	assert!(matches!(&map.layers[1], TiledLayer::Group { name, layers, .. } if name == "triggers" && layers.len() == 1));
+	assert!(map.to_tmx_string().contains("<group id=\"3\" name=\"triggers\">"));
+
+	// tiles have the cell size shared by the layers, even if the first layer is empty
+	let empty = image::RgbaImage::from_pixel(img.width() * 4, img.height() * 4, image::Rgba([255, 255, 255, 255]));
+	let legend = Legend { cell_size: CellSize::Auto, ..Legend::default() };
+	let layered = get_layered_layout_by_images(&[("markers", &empty), ("walls", &upscale(&img, 4))], &legend).unwrap();
+	let map = TiledMap::from_layout(&layered, TiledComplex::Polygon);
+	assert_eq!((map.width, map.height, map.tile_size), (img.width() as usize, img.height() as usize, 4));
+	assert_eq!(LdtkProject::from_layout(&layered, &legend).grid_size, 4);
+}
+
+#[test]
+fn test_ldtk_project() {
+	let legend = Legend::from_ron(r##"(
+		strict: false,
+		layers: {
+			"wall": (color: "#FF0000"),
+			"2 coins": (color: "#0000FF", entity: Some((max: Some(5)))),
+			"wire": (color: "#00FF00", connection: true),
+		},
+	)"##).unwrap();
+
+	let mut img = image::RgbaImage::from_pixel(4, 3, image::Rgba([255, 255, 255, 255]));
+	for x in 0 .. 4 {
+		img.put_pixel(x, 2, image::Rgba([255, 0, 0, 255]));
+	}
+	img.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+	img.put_pixel(3, 0, image::Rgba([0, 0, 255, 255]));
+	img.put_pixel(0, 1, image::Rgba([10, 20, 30, 255]));
+
+	let layout = utils::get_shapes_layout_by_rgba_image(&img, &legend).unwrap();
+	let project = LdtkProject::from_layout(&layout, &legend);
+
+	assert_eq!((project.width, project.height, project.grid_size), (4, 3, 1));
+	assert_eq!(project.layers, vec![
+		LdtkLayer::Entities {
+			identifier: "_2_coins".to_string(),
+			color: Color::new(0, 0, 255),
+			max_count: Some(5),
+			entities: vec![BBox::new_xy(1, 0, 1, 0), BBox::new_xy(3, 0, 3, 0)],
+		},
+		LdtkLayer::IntGrid { identifier: "wall".to_string(), color: Color::new(255, 0, 0), values: vec![0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1] },
+		LdtkLayer::IntGrid { identifier: "_0A141E".to_string(), color: Color::new(10, 20, 30), values: vec![0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0] },
+	]);
+
+	let ldtk: serde_json::Value = serde_json::from_str(&project.to_ldtk_string().unwrap()).unwrap();
+	let level = &ldtk["levels"][0];
+	assert_eq!((level["pxWid"].as_u64(), level["pxHei"].as_u64()), (Some(4), Some(3)));
+
+	let layer_defs = ldtk["defs"]["layers"].as_array().unwrap();
+	let layer_instances = level["layerInstances"].as_array().unwrap();
+	assert_eq!(layer_defs.len(), 3);
+	assert_eq!(layer_instances.len(), 3);
+	for (layer_def, layer_instance) in layer_defs.iter().zip(layer_instances) {
+		assert_eq!(layer_def["uid"], layer_instance["layerDefUid"]);
+		assert_eq!(layer_def["identifier"], layer_instance["__identifier"]);
+	}
+
+	let entity_def = &ldtk["defs"]["entities"][0];
+	assert_eq!(entity_def["maxCount"], 5);
+	assert_eq!(layer_instances[0]["entityInstances"][1]["px"], serde_json::json!([3, 0]));
+	assert_eq!(layer_instances[0]["entityInstances"][1]["defUid"], entity_def["uid"]);
+	assert_eq!(layer_defs[1]["intGridValues"][0]["color"], "#FF0000");
+	assert_eq!(layer_instances[1]["intGridCsv"], serde_json::json!([0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1]));
+
+	// uids and iids are unique
+	let mut uids: Vec<u64> = layer_defs.iter().chain(ldtk["defs"]["entities"].as_array().unwrap()).chain(std::iter::once(level))
+		.map(|def| def["uid"].as_u64().unwrap())
+		.collect();
+	uids.sort_unstable();
+	uids.dedup();
+	assert_eq!(uids.len(), 5);
+	assert!(ldtk["nextUid"].as_u64().unwrap() > 5);
+
+	// IntGrid layers of image layers are prefixed by their names, entities of all images share their layers
+	let layered = get_layered_layout_by_images(&[("front", &img), ("back", &img)], &legend).unwrap();
+	let project = LdtkProject::from_layout(&layered, &legend);
+	let identifiers: Vec<&str> = project.layers.iter()
+		.map(|layer| match layer {
+			LdtkLayer::IntGrid { identifier, .. } | LdtkLayer::Entities { identifier, .. } => identifier.as_str(),
+		})
+		.collect();
+	assert_eq!(identifiers, vec!["_2_coins", "front_wall", "front__0A141E", "back_wall", "back__0A141E"]);
+	assert!(matches!(&project.layers[0], LdtkLayer::Entities { entities, .. } if entities.len() == 2 * layout.entities.len()));
+}
+
+#[test]
+fn test_svg_rendering() {
+	let mut img = image::RgbaImage::from_pixel(5, 5, image::Rgba([255, 255, 255, 255]));
+	for (x, y) in [(0, 0), (1, 0), (2, 0), (0, 1), (2, 1), (0, 2), (1, 2), (2, 2), (0, 3), (4, 4)] {
+		img.put_pixel(x, y, image::Rgba([0, 0, 0, 255]));
+	}
+	img.put_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+
+	let layout = utils::get_shapes_layout_by_rgba_image(&img, &Legend::default()).unwrap();
+	let options = SvgOptions { scale: 10, ..Default::default() };
+	let svg = layout.to_svg_string(&[&img], &options);
+
+	assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"50\" height=\"50\""));
+	assert!(svg.ends_with("</svg>\n"));
+	// source pixels are drawn in runs of a row
+	assert!(svg.contains("<rect x=\"10\" y=\"10\" width=\"10\" height=\"10\" fill=\"#FF0000\"/>"));
+	assert!(svg.contains("<rect x=\"0\" y=\"0\" width=\"30\" height=\"10\" fill=\"#000000\"/>"));
+	assert!(svg.contains("<rect x=\"10\" y=\"30\" width=\"40\" height=\"10\" fill=\"#FFFFFF\"/>"));
+	// single points of shapes are marked with circles
+	assert!(svg.contains("<circle cx=\"45\" cy=\"45\" r=\"2.5\" fill=\"#FFFFFF\"/>"));
+	assert!(svg.contains(">0 #000000</text>"));
+	assert!(svg.contains(">1 #000000</text>"));
+	assert!(svg.contains(">0 #FF0000</text>"));
+	assert!(!svg.contains("<path"));
+	assert!(!svg.contains("<line"));
+
+	let covered: usize = layout.shapes[&Color::new(0, 0, 0)].iter().map(|shape| shape.get_covered_points().len()).sum();
+	let pieces = layout.shapes[&Color::new(0, 0, 0)].iter()
+		.map(|shape| match shape {
+			Shape::Complex(geom) => geom.get_bboxes().len() + geom.get_points().len(),
+			_ => 1,
+		})
+		.sum::<usize>();
+	// boxes are outlined and points are marked over the pixels, the red pixel is marked too
+	assert_eq!(svg.matches("fill=\"none\" stroke=\"#FFFFFF\"").count() + svg.matches("<circle").count(), pieces + 1);
+	assert_eq!(covered, 10);
+
+	let svg = layout.to_svg_string(&[&img], &SvgOptions::from_overlays("outlines, holes,dependencies").unwrap());
+	assert!(svg.contains("<path d=\"M0 0 L48 0 L48 48 L16 48 L16 64 L0 64 Z\" fill=\"none\" stroke=\"#0080FF\" stroke-width=\"2\"/>"));
+	assert!(svg.contains("<path d=\"M16 16 L16 32 L32 32 L32 16 Z\" fill=\"#FF00FF\""));
+	// the red pixel touches the black ring only
+	assert_eq!(svg.matches("<line").count(), 1);
+	assert!(svg.contains("<line x1=\"24\" y1=\"32\" x2=\"24\" y2=\"24\""));
+
+	assert_eq!(SvgOptions::from_overlays("all"), SvgOptions::from_overlays("holes,outlines,dependencies"));
+	assert_eq!(SvgOptions::from_overlays("outlines,edges"), None);
+
+	let layered = get_layered_layout_by_images(&[("walls", &img), ("<decor>", &img)], &Legend::default()).unwrap();
+	let svg = layered.to_svg_string(&[&img, &img], &options);
+	assert_eq!(svg.matches("<svg").count(), 1);
+	assert!(svg.contains("<g id=\"walls\">"));
+	assert!(svg.contains("<g id=\"&lt;decor&gt;\">"));
+	assert_eq!(svg.matches("<g class=\"source\">").count(), 2);
+}
+
+#[test]
+fn test_svg_corrupted_layout() {
+	let img = upscale(&image::open("assets/black_sample.png").unwrap().to_rgba(), 2);
+	let legend = Legend { cell_size: CellSize::Auto, ..Default::default() };
+	let source = utils::get_layout_image(&img, &legend).unwrap().into_owned();
+	let layout = utils::get_shapes_layout_by_rgba_image(&img, &legend).unwrap();
+	assert_eq!(source.dimensions(), (layout.width as u32, layout.height as u32));
+
+	let options = SvgOptions { scale: 16, ..Default::default() };
+	let get_source_group = |svg: &str| svg[svg.find("<g class=\"source\">").unwrap() ..].split("</g>").next().unwrap().to_string();
+
+	// a shape dropped and another one moved to empty pixels
+	let mut corrupted = utils::get_shapes_layout_by_rgba_image(&img, &legend).unwrap();
+	let black = corrupted.shapes.get_mut(&Color::BLACK).unwrap();
+	let dropped = match black.remove(black.iter().position(|shape| matches!(shape, Shape::Box(_))).unwrap()) {
+		Shape::Box(bbox) => bbox,
+		_ => unreachable!(),
+	};
+	let moved = (0 .. layout.height)
+		.flat_map(|y| (0 .. layout.width).map(move |x| Point::new(x, y)))
+		.find(|point| source.get_pixel(point.x as u32, point.y as u32).0[3] == 0)
+		.unwrap();
+	black.push(Shape::Pixel(moved));
+
+	let svg = layout.to_svg_string(&[&source], &options);
+	let corrupted_svg = corrupted.to_svg_string(&[&source], &options);
+
+	// the source pixels are the same, the pieces on top of them aren't
+	assert_eq!(get_source_group(&svg), get_source_group(&corrupted_svg));
+	assert!(get_source_group(&svg).contains("fill=\"#000000\""));
+	assert_ne!(svg, corrupted_svg);
+
+	// outlines are drawn inside the pixels with a stroke of 2 units
+	let outline = format!("<rect x=\"{}\" y=\"{}\" width=\"{}\"", dropped.min.x * 16 + 1, dropped.min.y * 16 + 1, dropped.get_width() * 16 - 2);
+	assert!(svg.contains(&outline));
+	assert!(!corrupted_svg.contains(&outline));
+
+	let marker = |point: &Point| format!("<circle cx=\"{}\" cy=\"{}\"", point.x * 16 + 8, point.y * 16 + 8);
+	assert!(!svg.contains(&marker(&moved)));
+	assert!(corrupted_svg.contains(&marker(&moved)));
+
+	assert!(!corrupted.verify(&img, &legend).unwrap().is_exact());
+}
+
+#[test]
+fn test_render_verification() {
+	let mut assets: Vec<std::path::PathBuf> = std::fs::read_dir("assets").unwrap()
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| path.extension().is_some_and(|extension| extension == "png"))
+		.collect();
+	assets.sort();
+	assert!(!assets.is_empty());
+
+	for path in &assets {
+		let img = image::open(path).unwrap().to_rgba();
+		let legend_path = path.with_extension("legend.ron");
+		let legend = if legend_path.exists() { Legend::read(&legend_path).unwrap() } else { Legend::default() };
+
+		for decomposition in &[Decomposition::TallestColumn, Decomposition::RowMerge, Decomposition::MinimumRectangles] {
+			for connectivity in &[Connectivity::Four, Connectivity::Eight] {
+				let legend = Legend { decomposition: *decomposition, connectivity: *connectivity, ..legend.clone() };
+				let layout = utils::get_shapes_layout_by_rgba_image(&img, &legend).unwrap();
+
+				let verification = layout.verify(&img, &legend).unwrap();
+				assert!(verification.is_exact(), "{}, {:?}, {:?}: {:?}", path.display(), decomposition, connectivity, verification);
+
+				// shape pixels keep their colors, empty ones are transparent
+				let rendering = layout.render_to_image(img.width(), img.height());
+				for (x, y, pixel) in img.enumerate_pixels() {
+					let expected = legend.get_pixel_color(&pixel.0).map_or([0, 0, 0, 0], |color| [color.r, color.g, color.b, color.a]);
+					assert_eq!(rendering.get_pixel(x, y).0, expected, "{} at ({}, {})", path.display(), x, y);
+				}
+			}
+		}
+	}
+
+	// cells are painted as blocks of pixels
+	let img = image::open("assets/black_sample.png").unwrap().to_rgba();
+	let blocks = upscale(&img, 3);
+	let legend = Legend { cell_size: CellSize::Fixed(3), ..Legend::default() };
+	let layout = utils::get_shapes_layout_by_rgba_image(&blocks, &legend).unwrap();
+	assert!(layout.verify(&blocks, &legend).unwrap().is_exact());
+
+	let rendering = layout.render_to_image(blocks.width(), blocks.height());
+	for (x, y, pixel) in blocks.enumerate_pixels() {
+		let expected = legend.get_pixel_color(&pixel.0).map_or([0, 0, 0, 0], |color| [color.r, color.g, color.b, color.a]);
+		assert_eq!(rendering.get_pixel(x, y).0, expected);
+	}
+	assert_eq!(layout.render_to_image(2, 1).dimensions(), (2, 1));
+
+	// broken layouts
+	let mut img = image::RgbaImage::from_pixel(4, 2, image::Rgba([255, 255, 255, 255]));
+	for x in 0 .. 3 {
+		img.put_pixel(x, 0, image::Rgba([0, 0, 0, 255]));
+	}
+	let black = Color::new(0, 0, 0);
+	let mut layout = utils::get_shapes_layout_by_rgba_image(&img, &Legend::default()).unwrap();
+	assert_eq!(layout.shapes[&black], vec![Shape::Box(BBox::new_xy(0, 0, 2, 0))]);
+
+	layout.shapes.insert(black, vec![Shape::Box(BBox::new_xy(0, 0, 1, 0)), Shape::Box(BBox::new_xy(1, 0, 1, 1)), Shape::Pixel(Point::new(5, 0))]);
+	let verification = layout.verify(&img, &Legend::default()).unwrap();
+	assert!(!verification.is_exact());
+	assert_eq!(verification.missing, vec![(Point::new(2, 0), black)]);
+	assert_eq!(verification.extra, vec![(Point::new(5, 0), black), (Point::new(1, 1), black)]);
+	assert_eq!(verification.double_covered, vec![Point::new(1, 0)]);
+
+	// entities aren't shapes
+	let legend = Legend::from_ron(r##"(layers: {"spawn": (color: "#000000", entity: Some(()))})"##).unwrap();
+	let layout = utils::get_shapes_layout_by_rgba_image(&img, &legend).unwrap();
+	assert!(layout.shapes.is_empty());
+	assert!(layout.verify(&img, &legend).unwrap().is_exact());
+}
@@ -0,0 +1,428 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use serde_json::json;
+
+use crate::entities::Entity;
+use crate::error::{Error, Result};
+use crate::legend::{Properties, Property};
+use crate::types::{
+	BBox,
+	Color,
+	Layer,
+	Shape,
+	ShapesLayout,
+};
+
+const TILED_VERSION: &str = "1.10";
+
+/// How complex shapes are written to Tiled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TiledComplex
+{
+	/// A polygon of the shape outline. Shapes with holes can't be a single polygon and are written as rectangles.
+	#[default]
+	Polygon,
+	/// A rectangle per piece of the shape, all of them having the shape index as the `shape` property.
+	Rects,
+}
+
+impl TiledComplex
+{
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"polygon" | "Polygon" => Some(TiledComplex::Polygon),
+			"rects" | "Rects" => Some(TiledComplex::Rects),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TiledObjectKind
+{
+	Rect { width: f64, height: f64 },
+	Point,
+	/// Vertices relative to the object position.
+	Polygon(Vec<(f64, f64)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TiledObject
+{
+	pub id: u32,
+	pub name: String,
+	pub x: f64,
+	pub y: f64,
+	pub kind: TiledObjectKind,
+	pub properties: Properties,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TiledLayer
+{
+	Objects { id: u32, name: String, color: Color, properties: Properties, objects: Vec<TiledObject> },
+	Group { id: u32, name: String, layers: Vec<TiledLayer> },
+}
+
+/// Orthogonal Tiled map without tilesets, a tile is a pixel of the layout
+/// and coordinates of objects are pixels of the source image.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TiledMap
+{
+	/// Size of the map in tiles.
+	pub width: usize,
+	pub height: usize,
+	/// Size of a tile in image pixels, the layout cell size.
+	pub tile_size: u32,
+	pub layers: Vec<TiledLayer>,
+	pub next_layer_id: u32,
+	pub next_object_id: u32,
+}
+
+impl TiledMap
+{
+	/// Object layer of every color named after its legend layer or its hex value, in a group layer
+	/// of every image layer of a layered layout, followed by a layer of every kind of entities.
+	pub fn from_layout(layout: &ShapesLayout, complex: TiledComplex) -> Self {
+		let mut map = Self::new(layout.width, layout.height, layout.cell_size.unwrap_or(1));
+
+		if layout.image_layers.is_empty() {
+			map.layers = map.get_shape_layers(layout, &layout.shapes, complex);
+		}
+
+		for image_layer in &layout.image_layers {
+			let id = map.get_layer_id();
+			let layers = map.get_shape_layers(layout, &image_layer.shapes, complex);
+			map.layers.push(TiledLayer::Group { id, name: image_layer.name.clone(), layers });
+		}
+
+		let entity_layers = map.get_entity_layers(layout);
+		map.layers.extend(entity_layers);
+		map
+	}
+
+	fn new(width: usize, height: usize, tile_size: u32) -> Self {
+		Self { width, height, tile_size, layers: Vec::new(), next_layer_id: 1, next_object_id: 1 }
+	}
+
+	fn get_layer_id(&mut self) -> u32 {
+		self.next_layer_id += 1;
+		self.next_layer_id - 1
+	}
+
+	fn get_object_id(&mut self) -> u32 {
+		self.next_object_id += 1;
+		self.next_object_id - 1
+	}
+
+	/// Object layer of every color of `shapes`, the layout or one of its image layers.
+	fn get_shape_layers(&mut self, layout: &ShapesLayout, shapes: &BTreeMap<Color, Vec<Shape>>, complex: TiledComplex) -> Vec<TiledLayer> {
+		let layers_by_color: HashMap<Color, (&String, &Layer)> = layout.layers.iter()
+			.map(|(name, layer)| (layer.color, (name, layer)))
+			.collect();
+
+		let mut layers = Vec::new();
+
+		for (color, shapes) in shapes {
+			let (name, properties) = match layers_by_color.get(color) {
+				Some((name, layer)) => (name.to_string(), layer.properties.clone()),
+				None => (color.to_string(), Properties::new()),
+			};
+
+			let id = self.get_layer_id();
+			let mut objects = Vec::new();
+			for (index, shape) in shapes.iter().enumerate() {
+				self.add_shape_objects(&mut objects, shape, index, complex);
+			}
+
+			layers.push(TiledLayer::Objects { id, name, color: *color, properties, objects });
+		}
+
+		layers
+	}
+
+	fn get_entity_layers(&mut self, layout: &ShapesLayout) -> Vec<TiledLayer> {
+		let mut layers = Vec::new();
+
+		for (kind, layer) in &layout.layers {
+			let entities: Vec<&Entity> = layout.get_entities(kind).collect();
+			if entities.is_empty() {
+				continue;
+			}
+
+			let id = self.get_layer_id();
+			let objects = entities.iter()
+				.map(|entity| {
+					let mut object = self.get_bbox_object(&entity.bbox);
+					object.name = kind.clone();
+					object
+				})
+				.collect();
+
+			layers.push(TiledLayer::Objects { id, name: kind.clone(), color: layer.color, properties: layer.properties.clone(), objects });
+		}
+
+		layers
+	}
+
+	fn get_object(&mut self, x: f64, y: f64, kind: TiledObjectKind) -> TiledObject {
+		TiledObject { id: self.get_object_id(), name: String::new(), x, y, kind, properties: Properties::new() }
+	}
+
+	fn get_rect_object(&mut self, bbox: &BBox) -> TiledObject {
+		let size = self.tile_size as f64;
+		let kind = TiledObjectKind::Rect { width: bbox.get_width() as f64 * size, height: bbox.get_height() as f64 * size };
+
+		self.get_object(bbox.min.x as f64 * size, bbox.min.y as f64 * size, kind)
+	}
+
+	/// Rectangle object of `bbox`, or a point object at the center of a single pixel.
+	fn get_bbox_object(&mut self, bbox: &BBox) -> TiledObject {
+		if bbox.is_point() {
+			let size = self.tile_size as f64;
+			self.get_object((bbox.min.x as f64 + 0.5) * size, (bbox.min.y as f64 + 0.5) * size, TiledObjectKind::Point)
+		} else {
+			self.get_rect_object(bbox)
+		}
+	}
+
+	fn add_shape_objects(&mut self, objects: &mut Vec<TiledObject>, shape: &Shape, index: usize, complex: TiledComplex) {
+		match shape {
+			Shape::Pixel(point) => objects.push(self.get_bbox_object(&BBox::new(point, point))),
+			Shape::Box(bbox) => objects.push(self.get_bbox_object(bbox)),
+			Shape::Complex(geom) if complex == TiledComplex::Polygon && geom.get_holes().is_empty() => {
+				let size = self.tile_size as f64;
+				let boundary = shape.get_outlines().remove(0).boundary;
+				let origin = boundary[0];
+
+				let vertices = boundary.iter()
+					.map(|corner| ((corner.x as f64 - origin.x as f64) * size, (corner.y as f64 - origin.y as f64) * size))
+					.collect();
+
+				objects.push(self.get_object(origin.x as f64 * size, origin.y as f64 * size, TiledObjectKind::Polygon(vertices)));
+			},
+			Shape::Complex(geom) => {
+				// single pixels of the shape are areas as well
+				let bboxes = geom.get_bboxes().iter().cloned().chain(geom.get_points().iter().map(|point| BBox::new(point, point)));
+
+				for bbox in bboxes {
+					let mut object = self.get_rect_object(&bbox);
+					object.properties.insert("shape".to_string(), Property::Int(index as i64));
+					objects.push(object);
+				}
+			},
+		}
+	}
+
+	pub fn to_tmx_string(&self) -> String {
+		let mut tmx = String::new();
+
+		tmx.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+		let _ = writeln!(
+			tmx,
+			"<map version=\"{}\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" infinite=\"0\" nextlayerid=\"{}\" nextobjectid=\"{}\">",
+			TILED_VERSION, self.width, self.height, self.tile_size, self.tile_size, self.next_layer_id, self.next_object_id,
+		);
+
+		for layer in &self.layers {
+			write_tmx_layer(&mut tmx, layer, 1);
+		}
+
+		tmx.push_str("</map>\n");
+		tmx
+	}
+
+	pub fn to_tmj_string(&self) -> Result<String> {
+		let map = json!({
+			"type": "map",
+			"version": TILED_VERSION,
+			"orientation": "orthogonal",
+			"renderorder": "right-down",
+			"width": self.width,
+			"height": self.height,
+			"tilewidth": self.tile_size,
+			"tileheight": self.tile_size,
+			"infinite": false,
+			"nextlayerid": self.next_layer_id,
+			"nextobjectid": self.next_object_id,
+			"layers": self.layers.iter().map(get_tmj_layer).collect::<Vec<_>>(),
+			"tilesets": [],
+		});
+
+		serde_json::to_string_pretty(&map).map_err(Error::Json)
+	}
+}
+
+/// Tiled colors put alpha first.
+fn get_tiled_color(color: &Color) -> String {
+	if color.a == 255 {
+		format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+	} else {
+		format!("#{:02x}{:02x}{:02x}{:02x}", color.a, color.r, color.g, color.b)
+	}
+}
+
+fn get_property_type(property: &Property) -> &'static str {
+	match property {
+		Property::Bool(_) => "bool",
+		Property::Int(_) => "int",
+		Property::Float(_) => "float",
+		Property::String(_) => "string",
+	}
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+	text.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&apos;")
+}
+
+fn write_tmx_properties(tmx: &mut String, properties: &Properties, depth: usize) {
+	if properties.is_empty() {
+		return;
+	}
+
+	let indent = " ".repeat(depth);
+
+	let _ = writeln!(tmx, "{}<properties>", indent);
+	for (name, property) in properties {
+		let value = match property {
+			Property::Bool(value) => value.to_string(),
+			Property::Int(value) => value.to_string(),
+			Property::Float(value) => value.to_string(),
+			Property::String(value) => escape_xml(value),
+		};
+
+		let _ = writeln!(tmx, "{} <property name=\"{}\" type=\"{}\" value=\"{}\"/>", indent, escape_xml(name), get_property_type(property), value);
+	}
+	let _ = writeln!(tmx, "{}</properties>", indent);
+}
+
+fn write_tmx_layer(tmx: &mut String, layer: &TiledLayer, depth: usize) {
+	let indent = " ".repeat(depth);
+
+	match layer {
+		TiledLayer::Objects { id, name, color, properties, objects } => {
+			let _ = writeln!(tmx, "{}<objectgroup id=\"{}\" name=\"{}\" color=\"{}\">", indent, id, escape_xml(name), get_tiled_color(color));
+			write_tmx_properties(tmx, properties, depth + 1);
+
+			for object in objects {
+				let _ = write!(tmx, "{} <object id=\"{}\"", indent, object.id);
+				if !object.name.is_empty() {
+					let _ = write!(tmx, " name=\"{}\"", escape_xml(&object.name));
+				}
+				let _ = write!(tmx, " x=\"{}\" y=\"{}\"", object.x, object.y);
+				if let TiledObjectKind::Rect { width, height } = object.kind {
+					let _ = write!(tmx, " width=\"{}\" height=\"{}\"", width, height);
+				}
+
+				if object.properties.is_empty() && matches!(object.kind, TiledObjectKind::Rect { .. }) {
+					tmx.push_str("/>\n");
+					continue;
+				}
+
+				tmx.push_str(">\n");
+				write_tmx_properties(tmx, &object.properties, depth + 2);
+
+				match &object.kind {
+					TiledObjectKind::Rect { .. } => {},
+					TiledObjectKind::Point => {
+						let _ = writeln!(tmx, "{}  <point/>", indent);
+					},
+					TiledObjectKind::Polygon(vertices) => {
+						let points: Vec<String> = vertices.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+						let _ = writeln!(tmx, "{}  <polygon points=\"{}\"/>", indent, points.join(" "));
+					},
+				}
+
+				let _ = writeln!(tmx, "{} </object>", indent);
+			}
+
+			let _ = writeln!(tmx, "{}</objectgroup>", indent);
+		},
+		TiledLayer::Group { id, name, layers } => {
+			let _ = writeln!(tmx, "{}<group id=\"{}\" name=\"{}\">", indent, id, escape_xml(name));
+			for layer in layers {
+				write_tmx_layer(tmx, layer, depth + 1);
+			}
+			let _ = writeln!(tmx, "{}</group>", indent);
+		},
+	}
+}
+
+fn get_tmj_properties(properties: &Properties) -> serde_json::Value {
+	properties.iter()
+		.map(|(name, property)| json!({ "name": name, "type": get_property_type(property), "value": property }))
+		.collect()
+}
+
+fn get_tmj_layer(layer: &TiledLayer) -> serde_json::Value {
+	match layer {
+		TiledLayer::Objects { id, name, color, properties, objects } => {
+			let objects: Vec<serde_json::Value> = objects.iter()
+				.map(|object| {
+					let mut value = json!({
+						"id": object.id,
+						"name": object.name,
+						"type": "",
+						"x": object.x,
+						"y": object.y,
+						"width": 0,
+						"height": 0,
+						"rotation": 0,
+						"visible": true,
+					});
+
+					match &object.kind {
+						TiledObjectKind::Rect { width, height } => {
+							value["width"] = json!(width);
+							value["height"] = json!(height);
+						},
+						TiledObjectKind::Point => value["point"] = json!(true),
+						TiledObjectKind::Polygon(vertices) => {
+							value["polygon"] = vertices.iter().map(|(x, y)| json!({ "x": x, "y": y })).collect();
+						},
+					}
+
+					if !object.properties.is_empty() {
+						value["properties"] = get_tmj_properties(&object.properties);
+					}
+
+					value
+				})
+				.collect();
+
+			let mut value = json!({
+				"type": "objectgroup",
+				"id": id,
+				"name": name,
+				"color": get_tiled_color(color),
+				"draworder": "topdown",
+				"opacity": 1,
+				"visible": true,
+				"x": 0,
+				"y": 0,
+				"objects": objects,
+			});
+
+			if !properties.is_empty() {
+				value["properties"] = get_tmj_properties(properties);
+			}
+
+			value
+		},
+		TiledLayer::Group { id, name, layers } => json!({
+			"type": "group",
+			"id": id,
+			"name": name,
+			"opacity": 1,
+			"visible": true,
+			"x": 0,
+			"y": 0,
+			"layers": layers.iter().map(get_tmj_layer).collect::<Vec<_>>(),
+		}),
+	}
+}
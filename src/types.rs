@@ -1,11 +1,30 @@
 #![allow(dead_code)]
 
+pub use std::collections::BTreeMap;
+pub use std::collections::BTreeSet;
 pub use std::collections::HashMap;
 pub use std::collections::HashSet;
 
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::Path;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error as _;
+
+use crate::connections::Connection;
+use crate::contour::{self, Hole, Outline};
+use crate::decomposition::{Decomposition, DecompositionStrategy, SplittedComplexGeometry};
+use crate::entities::Entity;
+use crate::error::{self, Error};
+use crate::json;
+use crate::labeling::Connectivity;
+use crate::layered::ImageLayer;
+use crate::legend::Properties;
+use crate::palette::SnapReport;
 use crate::utils;
 
-#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Point
 {
     pub x: usize,
@@ -41,7 +60,22 @@ impl Default for Point
 	}
 }
 
-#[derive(Eq, PartialEq, Hash, Default, Clone, Copy, Debug)]
+// points are ordered the way they are scanned: row by row, left to right
+impl Ord for Point
+{
+	fn cmp(&self, other: &Self) -> Ordering {
+		(self.y, self.x).cmp(&(other.y, other.x))
+	}
+}
+
+impl PartialOrd for Point
+{
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Default, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct BBox
 {
 	pub min: Point,
@@ -90,255 +124,141 @@ impl BBox
 	pub fn get_points_count(&self) -> usize {
 		self.get_square()
 	}
+
+	/// Boxes overlap or touch by a side or a corner.
+	pub fn is_near(&self, other: &BBox) -> bool {
+		self.min.x <= other.max.x + 1 && other.min.x <= self.max.x + 1 &&
+		self.min.y <= other.max.y + 1 && other.min.y <= self.max.y + 1
+	}
 }
 
-#[derive(Default, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+/// Color of layout pixels. Alpha is `255` unless the legend keeps alpha of pixels as data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Color
 {
 	pub r: u8,
 	pub g: u8,
 	pub b: u8,
+	pub a: u8,
 }
 
 impl Color
 {
 	pub const BLACK: Self = Color::new(0, 0, 0);
+	pub const WHITE: Self = Color::new(255, 255, 255);
 
 	pub const fn new(r: u8, g: u8, b:u8) -> Self {
-		Color {r, g, b}
+		Color {r, g, b, a: 255}
 	}
-}
 
-pub type NeighboursMap = HashMap<Point, (Color, Vec<Point>)>;
-
-#[derive(Clone, Debug, Default)]
-pub struct ComplexGeometry
-{
-	bboxes: HashSet<BBox>,
-	points: HashSet<Point>,
-	outer_bbox: BBox,
-}
-
-#[derive(Clone, Debug, Default)]
-struct SplittedComplexGeometry
-{
-	bboxes: HashSet<BBox>,
-	points: HashSet<Point>,
-}
-
-impl SplittedComplexGeometry
-{
-	pub fn new(points: &HashSet<Point>, outer_bbox: &BBox) -> Self {
-		let mut obj = SplittedComplexGeometry::default();
-		obj._init(points, outer_bbox);
-		obj
+	pub const fn new_rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+		Color {r, g, b, a}
 	}
 
-	pub fn merge(&mut self, other: SplittedComplexGeometry) {
-		self.points.extend(other.points);
-		self.bboxes.extend(other.bboxes);
+	/// Same color with alpha discarded.
+	pub const fn to_opaque(&self) -> Self {
+		Color::new(self.r, self.g, self.b)
 	}
 
-	fn _init(&mut self, points: &HashSet<Point>, outer_bbox: &BBox) {
-		if points.is_empty() {
-			return;
-		}
-
-		// find the heightiest column of pixels in all shape
-		// then we'll find the largest bbox of all shape with this height
-		let mut min_y = outer_bbox.max.y;
-		let mut max_y = 0usize;
-		{
-			let mut max_height = 0usize;
-
-			for x in outer_bbox.min.x ..= outer_bbox.max.x {
-				let mut column_started = false;
-				let mut min_column_y: Option<usize> = None;
-				let mut max_column_y: Option<usize> = None;
-				let mut column_height = 0usize;
-
-				for y in outer_bbox.min.y ..= outer_bbox.max.y {
-					if points.contains(&Point::new(x, y)) {
-						if !column_started {
-							column_started = true;
-							min_column_y.replace(y);
-							column_height = 1;
-						} else {
-							max_column_y.replace(y);
-							column_height += 1;
-						}
-					} else {
-						if min_column_y.is_some() {
-							if column_height > max_height {
-								max_height = column_height;
-								min_y = min_column_y.unwrap();
-								max_y = max_column_y.unwrap_or(min_y);
-							}
-						}
-						column_height = 0;
-						column_started = false;
-						min_column_y = None;
-						max_column_y = None;
-					}
-				}
-
-				if column_height > max_height {
-					max_height = column_height;
-					min_y = min_column_y.unwrap();
-					max_y = max_column_y.unwrap_or(min_y);
-				}
-			}
+	/// Parses `#RRGGBB` or `#RRGGBBAA` (the leading `#` is optional).
+	pub fn from_hex(hex: &str) -> Option<Self> {
+		let hex = hex.strip_prefix('#').unwrap_or(hex);
+		if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+			return None;
 		}
 
-		// find heightest bboxes
-		let min_y = min_y;
-		let max_y = max_y;
-
-		let mut heightiest_bboxes = Vec::new();
-
-		let mut min_x: Option<usize> = None;
-		let mut max_x: Option<usize> = None;
-
-		for x in outer_bbox.min.x ..= outer_bbox.max.x {
-			let mut match_height: usize = 0;
-			for y in min_y ..= max_y {
-				let p = Point::new(x, y);
-				if points.contains(&p) {
-					match_height += 1;
-				} else {
-					break;
-				}
-			}
+		let channel = |i: usize| u8::from_str_radix(&hex[i .. i + 2], 16).ok();
+		let a = if hex.len() == 8 { channel(6)? } else { 255 };
 
-			if match_height == (max_y - min_y + 1) {
-				if min_x.is_none() {
-					min_x.replace(x);
-				} else {
-					max_x.replace(x);
-				}
-			} else {
-				if min_x.is_some() {
-					if max_x.is_none() {
-						max_x.replace(min_x.unwrap());
-					}
+		Some(Color::new_rgba(channel(0)?, channel(2)?, channel(4)?, a))
+	}
 
-					heightiest_bboxes.push(BBox::new_xy(min_x.take().unwrap(), min_y, max_x.take().unwrap(), max_y));
-				} else {
-					min_x = None;
-					max_x = None;
-				}
-			}
+	/// `#RRGGBB` for opaque colors, `#RRGGBBAA` otherwise.
+	pub fn to_hex(&self) -> String {
+		if self.a == 255 {
+			format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+		} else {
+			format!("#{:02X}{:02X}{:02X}{:02X}", self.r, self.g, self.b, self.a)
 		}
+	}
+}
 
-		if min_x.is_some() {
-			if max_x.is_none() {
-				max_x.replace(min_x.unwrap());
-			}
-
-			heightiest_bboxes.push(BBox::new_xy(min_x.take().unwrap(), min_y, max_x.take().unwrap(), max_y));
-		}
+impl Default for Color
+{
+	fn default() -> Self {
+		Color::BLACK
+	}
+}
 
-		for bbox in &heightiest_bboxes {
-			if bbox.is_point() {
-				self.points.insert(bbox.min);
-			} else {
-				self.bboxes.insert(*bbox);
-			}
-		}
+impl fmt::Display for Color
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.to_hex())
+	}
+}
 
-		// process rest shapes that did not belong to heightiest bboxes
-		{
-			let rest_shapes = Self::_split_points_by_bboxes(points.clone(), heightiest_bboxes);
-			for shape in &rest_shapes {
-				match shape {
-					Shape::Pixel(point) => {self.points.insert(*point);},
-					Shape::Box(bbox) => {self.bboxes.insert(*bbox);},
-					Shape::Complex(geom) => {self.merge(geom.copy_inner_geometry());},
-				}
-			}
-		}
+// colors are written as hex strings so they can be used as map keys in any format
+impl Serialize for Color
+{
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.to_hex())
 	}
+}
 
-	fn _split_points_by_bboxes(mut points: HashSet<Point>, heightiest_bboxes: Vec<BBox>) -> Vec<Shape> {
-		for bbox in heightiest_bboxes {
-			for x in bbox.min.x ..= bbox.max.x {
-				for y in bbox.min.y ..= bbox.max.y {
-					points.remove(&Point::new(x, y));
-				}
-			}
+impl<'de> Deserialize<'de> for Color
+{
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		fn opaque() -> u8 {
+			255
 		}
 
-		if points.is_empty() {
-			return Vec::new();
+		#[derive(Deserialize)]
+		#[serde(untagged)]
+		enum ColorRepr
+		{
+			Hex(String),
+			Rgb { r: u8, g: u8, b: u8, #[serde(default = "opaque")] a: u8 },
 		}
 
-		let neighbours_map = Self::_get_neighbours_map(&points);
-		let shapes = utils::get_shapes_by_neighbour_points(neighbours_map);
-
-		shapes.into_iter().flat_map(|x| x.1).map(|x| x).collect()
-	}
-
-	fn _get_neighbours_map(points: &HashSet<Point>) -> NeighboursMap {
-		let mut neighbours = NeighboursMap::new();
-
-		let bbox = utils::calc_bbox_by_points(points);
-
-		for y in bbox.min.y ..= bbox.max.y {
-			for x in bbox.min.x ..= bbox.max.x {
-				let p = Point::new(x, y);
-
-				if points.contains(&p) {
-					neighbours.insert(p, (Color::BLACK, Vec::new()));
-
-					let v = &mut neighbours.get_mut(&p).unwrap().1;
-
-					if let Some(neighbour) = p.get_neighbour(-1, 0) {
-						if points.contains(&neighbour) {
-							v.push(neighbour);
-						}
-					}
-
-					if let Some(neighbour) = p.get_neighbour(1, 0) {
-						if points.contains(&neighbour) {
-							v.push(neighbour);
-						}
-					}
-
-					if let Some(neighbour) = p.get_neighbour(0, -1) {
-						if points.contains(&neighbour) {
-							v.push(neighbour);
-						}
-					}
-
-					if let Some(neighbour) = p.get_neighbour(0, 1) {
-						if points.contains(&neighbour) {
-							v.push(neighbour);
-						}
-					}
-				}
-			}
+		match ColorRepr::deserialize(deserializer)? {
+			ColorRepr::Hex(hex) => Color::from_hex(&hex).ok_or_else(|| D::Error::custom(format!("invalid color `{}`, expected `#RRGGBB` or `#RRGGBBAA`", hex))),
+			ColorRepr::Rgb { r, g, b, a } => Ok(Color::new_rgba(r, g, b, a)),
 		}
-
-		neighbours
-	}
-
-	pub fn get_bboxes(&self) -> &HashSet<BBox> {
-		&self.bboxes
 	}
+}
 
-	pub fn get_points(&self) -> &HashSet<Point> {
-		&self.points
-	}
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ComplexGeometry
+{
+	bboxes: BTreeSet<BBox>,
+	points: BTreeSet<Point>,
+	outer_bbox: BBox,
+	/// Empty regions enclosed by the shape in scanline order.
+	#[serde(default)]
+	holes: Vec<Hole>,
+	#[serde(default)]
+	connectivity: Connectivity,
 }
 
 impl ComplexGeometry
 {
 	pub fn new(points: HashSet<Point>) -> Self {
+		Self::new_with_strategy(points, Decomposition::default().get_strategy())
+	}
+
+	pub fn new_with_strategy(points: HashSet<Point>, strategy: &dyn DecompositionStrategy) -> Self {
+		Self::new_with_options(points, strategy, Connectivity::Four)
+	}
+
+	/// Geometry of `points` connected with `connectivity`, it decides which empty regions are holes.
+	pub fn new_with_options(points: HashSet<Point>, strategy: &dyn DecompositionStrategy, connectivity: Connectivity) -> Self {
 		let mut n = Self {
+			connectivity,
 			..Self::default()
 		};
 		n._calc_outer_bbox(&points);
-		n._calc_inner_geometries(&points);
+		n._calc_inner_geometries(&points, strategy);
+		n._calc_holes(&points);
 
 		n
 	}
@@ -347,10 +267,16 @@ impl ComplexGeometry
 		self.outer_bbox = utils::calc_bbox_by_points(points);
 	}
 
-	fn _calc_inner_geometries(&mut self, points: &HashSet<Point>) {
-		let inner_geometry = SplittedComplexGeometry::new(points, &self.outer_bbox);
-		self.bboxes = inner_geometry.bboxes;
-		self.points = inner_geometry.points;
+	fn _calc_inner_geometries(&mut self, points: &HashSet<Point>, strategy: &dyn DecompositionStrategy) {
+		let (bboxes, points) = strategy.split(points, &self.outer_bbox).into_parts();
+		self.bboxes = bboxes;
+		self.points = points;
+	}
+
+	fn _calc_holes(&mut self, points: &HashSet<Point>) {
+		self.holes = contour::trace_outlines(points, self.connectivity).iter()
+			.flat_map(Outline::get_holes)
+			.collect();
 	}
 
 	pub fn contains(&self, point: &Point) -> bool {
@@ -369,11 +295,11 @@ impl ComplexGeometry
 		false
 	}
 
-	pub fn get_bboxes(&self) -> &HashSet<BBox> {
+	pub fn get_bboxes(&self) -> &BTreeSet<BBox> {
 		&self.bboxes
 	}
 
-	pub fn get_points(&self) -> &HashSet<Point> {
+	pub fn get_points(&self) -> &BTreeSet<Point> {
 		&self.points
 	}
 
@@ -381,6 +307,14 @@ impl ComplexGeometry
 		&self.outer_bbox
 	}
 
+	pub fn get_holes(&self) -> &[Hole] {
+		&self.holes
+	}
+
+	pub fn get_connectivity(&self) -> Connectivity {
+		self.connectivity
+	}
+
 	pub fn try_get_as_bbox(&self) -> Option<BBox> {
 		let outer = &self.outer_bbox;
 		for x in outer.min.x ..= outer.max.x {
@@ -405,15 +339,12 @@ impl ComplexGeometry
 		None
 	}
 
-	fn copy_inner_geometry(&self) -> SplittedComplexGeometry {
-		SplittedComplexGeometry {
-			bboxes: self.bboxes.clone(),
-			points: self.points.clone()
-		}
+	pub fn copy_inner_geometry(&self) -> SplittedComplexGeometry {
+		SplittedComplexGeometry::new(self.bboxes.clone(), self.points.clone())
 	}
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Shape
 {
 	Pixel(Point),
@@ -441,13 +372,7 @@ impl Shape
 					return;
 				}
 			},
-			Shape::Box(bbox) => {
-				if bbox.is_point() {
-					Shape::Pixel(bbox.min)
-				} else {
-					return;
-				}
-			},
+			Shape::Box(bbox) if bbox.is_point() => Shape::Pixel(bbox.min),
 			_ => return,
 		}
 	}
@@ -457,11 +382,235 @@ impl Shape
 		cloned.simplify();
 		cloned
 	}
+
+	/// Top-left point of the shape in scanline order.
+	pub fn get_first_point(&self) -> Point {
+		match self {
+			Shape::Pixel(point) => *point,
+			Shape::Box(bbox) => bbox.min,
+			Shape::Complex(geom) => {
+				geom.bboxes.iter().map(|bbox| bbox.min)
+					.chain(geom.points.iter().cloned())
+					.min()
+					.unwrap_or_default()
+			},
+		}
+	}
+
+	pub fn get_bbox(&self) -> BBox {
+		match self {
+			Shape::Pixel(point) => BBox::new(point, point),
+			Shape::Box(bbox) => *bbox,
+			Shape::Complex(geom) => *geom.get_outer_bbox(),
+		}
+	}
+
+	/// The pixel `point` is one of the shape pixels.
+	pub fn contains(&self, point: &Point) -> bool {
+		match self {
+			Shape::Pixel(pixel) => pixel == point,
+			Shape::Box(bbox) => bbox.contains(point),
+			Shape::Complex(geom) => geom.contains(point),
+		}
+	}
+
+	/// Empty regions enclosed by the shape, only complex shapes may have them.
+	pub fn get_holes(&self) -> &[Hole] {
+		match self {
+			Shape::Complex(geom) => geom.get_holes(),
+			_ => &[],
+		}
+	}
+
+	/// Point of the layout lies in one of the shape holes.
+	pub fn encloses(&self, point: &Point) -> bool {
+		self.get_holes().iter().any(|hole| hole.contains(point))
+	}
+
+	/// Boxes the shape is made of, single points are one-pixel boxes.
+	pub fn get_pieces(&self) -> Vec<BBox> {
+		match self {
+			Shape::Pixel(point) => vec![BBox::new(point, point)],
+			Shape::Box(bbox) => vec![*bbox],
+			Shape::Complex(geom) => geom.bboxes.iter().cloned()
+				.chain(geom.points.iter().map(|point| BBox::new(point, point)))
+				.collect(),
+		}
+	}
+
+	pub fn get_covered_points(&self) -> HashSet<Point> {
+		self.get_pieces().iter()
+			.flat_map(|bbox| (bbox.min.y ..= bbox.max.y).flat_map(move |y| (bbox.min.x ..= bbox.max.x).map(move |x| Point::new(x, y))))
+			.collect()
+	}
+
+	/// Outline of every connected region of the shape; shapes of a layout are a single region.
+	pub fn get_outlines(&self) -> Vec<Outline> {
+		match self {
+			Shape::Pixel(point) => vec![Outline::from_bbox(&BBox::new(point, point))],
+			Shape::Box(bbox) => vec![Outline::from_bbox(bbox)],
+			Shape::Complex(geom) => contour::trace_outlines(&self.get_covered_points(), geom.connectivity),
+		}
+	}
+}
+
+/// Legend layer a color of the layout belongs to, its shapes are `shapes` of the color.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Layer
+{
+	pub color: Color,
+	pub properties: Properties,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning
+{
+	UnknownColor { color: Color, point: Point },
+	/// `count` pixels of `color` starting at `point` are too far from the snapping palette.
+	OutOfTolerance { color: Color, point: Point, count: usize },
+	/// Pixels of the cell have `colors_count` colors, `color` won the majority vote.
+	MixedCell { cell: Point, colors_count: usize, color: Option<Color> },
+	/// Line of a connection layer starting at `point` joins `shapes_count` shapes instead of two.
+	DanglingConnection { point: Point, shapes_count: usize },
+}
+
+impl fmt::Display for Warning
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Warning::UnknownColor { color, point } => write!(f, "color {} at ({}, {}) is not in the legend", color, point.x, point.y),
+			Warning::OutOfTolerance { color, point, count } => write!(f, "color {} of {} pixels starting at ({}, {}) is out of the palette tolerance", color, count, point.x, point.y),
+			Warning::MixedCell { cell, colors_count, color } => {
+				let color = color.map(|color| color.to_string()).unwrap_or_else(|| "empty".to_string());
+				write!(f, "cell ({}, {}) has {} colors, {} is used", cell.x, cell.y, colors_count, color)
+			},
+			Warning::DanglingConnection { point, shapes_count } => {
+				write!(f, "connection line at ({}, {}) joins {} shapes instead of 2", point.x, point.y, shapes_count)
+			},
+		}
+	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct ShapesLayout
 {
-	pub shapes: HashMap<Color, Vec<Shape>>,
-	pub color_dependencies: HashMap<Color, HashSet<Color>>
-}
\ No newline at end of file
+	/// Shapes of every color in scanline order of their top-left points, empty in a layered layout.
+	pub shapes: BTreeMap<Color, Vec<Shape>>,
+	/// Colors touching the key color by a side.
+	pub color_dependencies: BTreeMap<Color, BTreeSet<Color>>,
+	/// Colors the key color is fully enclosed by.
+	#[serde(default)]
+	pub color_enclosures: BTreeMap<Color, BTreeSet<Color>>,
+	/// Color and properties of every legend layer by its name, and of every color missing in the legend
+	/// by its hex value, so game code finds shapes by name with `get_layer_shapes`. Legend layers without pixels
+	/// are kept, so that a layer absent in the image isn't taken for a misspelled name.
+	#[serde(default)]
+	pub layers: BTreeMap<String, Layer>,
+	/// Instances of the legend entity layers, their shapes are not in `shapes`.
+	#[serde(default)]
+	pub entities: Vec<Entity>,
+	/// Shapes and entities joined by the lines of the legend connection layers.
+	#[serde(default)]
+	pub connections: Vec<Connection>,
+	/// Shapes of every image of a layered level in the order the images were given, see `get_layered_layout`.
+	/// Color relations and legend layers of a layered layout are joined from all images.
+	#[serde(default)]
+	pub image_layers: Vec<ImageLayer>,
+	/// Size of the layout in pixels.
+	#[serde(default)]
+	pub width: usize,
+	#[serde(default)]
+	pub height: usize,
+	/// Size of image blocks taken as one pixel of the layout, if it is not `1`.
+	#[serde(default)]
+	pub cell_size: Option<u32>,
+	#[serde(skip)]
+	pub warnings: Vec<Warning>,
+	/// Result of the colors snapping if the legend enables it.
+	#[serde(skip)]
+	pub snap_report: Option<SnapReport>,
+}
+
+impl ShapesLayout
+{
+	pub fn to_ron_string(&self) -> error::Result<String> {
+		ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new()).map_err(Error::Serialize)
+	}
+
+	pub fn write_ron<P: AsRef<Path>>(&self, path: P) -> error::Result<()> {
+		let ron = self.to_ron_string()?;
+		std::fs::write(path, ron)?;
+
+		Ok(())
+	}
+
+	pub fn from_ron(ron: &str) -> error::Result<Self> {
+		ron::de::from_str(ron).map_err(Error::Parse)
+	}
+
+	pub fn read_ron<P: AsRef<Path>>(path: P) -> error::Result<Self> {
+		let ron = std::fs::read_to_string(path)?;
+		Self::from_ron(&ron)
+	}
+
+	pub fn to_json_string(&self) -> error::Result<String> {
+		json::to_json_string(self)
+	}
+
+	pub fn write_json<P: AsRef<Path>>(&self, path: P) -> error::Result<()> {
+		let json = self.to_json_string()?;
+		std::fs::write(path, json)?;
+
+		Ok(())
+	}
+
+	pub fn from_json(json: &str) -> error::Result<Self> {
+		json::from_json(json)
+	}
+
+	pub fn read_json<P: AsRef<Path>>(path: P) -> error::Result<Self> {
+		let json = std::fs::read_to_string(path)?;
+		Self::from_json(&json)
+	}
+
+	pub fn are_adjacent(&self, color: &Color, other: &Color) -> bool {
+		self.color_dependencies.get(color).is_some_and(|colors| colors.contains(other))
+	}
+
+	pub fn is_enclosed_by(&self, color: &Color, other: &Color) -> bool {
+		self.color_enclosures.get(color).is_some_and(|colors| colors.contains(other))
+	}
+
+	/// Colors and indices of the shapes whose bounding box overlaps or touches `area`,
+	/// so the neighbours of `area` pixels are found without indexing every pixel of the layout.
+	pub(crate) fn get_shapes_near(&self, area: &BBox) -> Vec<(Color, usize)> {
+		get_shapes_near(&self.shapes, area)
+	}
+
+	/// Outlines of all shapes of `color` in the shapes order.
+	pub fn get_outlines(&self, color: &Color) -> Vec<Outline> {
+		self.shapes.get(color)
+			.map(|shapes| shapes.iter().flat_map(Shape::get_outlines).collect())
+			.unwrap_or_default()
+	}
+
+	pub fn get_layer(&self, name: &str) -> Option<&Layer> {
+		self.layers.get(name)
+	}
+
+	/// Shapes of the legend layer `name`, empty if the layer is known but absent in the image.
+	pub fn get_layer_shapes(&self, name: &str) -> Option<&[Shape]> {
+		let layer = self.layers.get(name)?;
+
+		Some(self.shapes.get(&layer.color).map(Vec::as_slice).unwrap_or(&[]))
+	}
+}
+
+/// Colors and indices of `shapes` whose bounding box overlaps or touches `area`.
+pub(crate) fn get_shapes_near(shapes: &BTreeMap<Color, Vec<Shape>>, area: &BBox) -> Vec<(Color, usize)> {
+	shapes.iter()
+		.flat_map(|(color, shapes)| shapes.iter().enumerate().map(move |(index, shape)| (*color, index, shape)))
+		.filter(|(_, _, shape)| shape.get_bbox().is_near(area))
+		.map(|(color, index, _)| (color, index))
+		.collect()
+}
@@ -1,159 +1,372 @@
 #![allow(dead_code)]
 
-use image::RgbaImage;
-
-use std::collections::HashMap;
-
+use image::{DynamicImage, ImageFormat, RgbaImage};
+
+use std::borrow::Cow;
+use std::io::{BufReader, Read, Seek};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::cells;
+use crate::decomposition::DecompositionStrategy;
+use crate::labeling::{self, Components, Connectivity, NO_LABEL};
+use crate::error::{Error, Result};
+use crate::legend::Legend;
+use crate::palette::SnapReport;
 use crate::types::{
-    NeighboursMap,
     Point,
     Shape,
     Color,
     ComplexGeometry,
     BBox,
     ShapesLayout,
+    Warning,
 };
 
-fn is_point_in_image(img: &RgbaImage, point: &Point) -> bool {
-    let (width, height) = img.dimensions();
-	let width = width as usize;
-	let height = height as usize;
+pub fn get_shapes_layout(img_path: &str) -> Result<ShapesLayout> {
+    let img = image::open(img_path)?.into_rgba();
 
-	let (x, y) = (point.x, point.y);
+    get_shapes_layout_by_image(&img, &Legend::default())
+}
 
-	x < width && y < height
+pub fn get_shapes_layout_with_legend(img_path: &str, legend: &Legend) -> Result<ShapesLayout> {
+    let img = image::open(img_path)?.into_rgba();
+
+    get_shapes_layout_by_rgba_image(&img, legend)
 }
 
-fn is_solid_color(rgba: &[u8; 4]) -> bool {
-	let is_transparent = rgba[3] != 255;
-	let is_white = is_transparent || (rgba[0] == 255 && rgba[1] == 255 && rgba[2] == 255);
+/// Layout of `img` with `legend` applied.
+pub fn get_shapes_layout_by_rgba_image(img: &RgbaImage, legend: &Legend) -> Result<ShapesLayout> {
+    let mut layout = get_shapes_layout_by_image(img, legend)?;
+    layout.apply_legend(legend)?;
 
-	!is_white
+    Ok(layout)
 }
 
-fn is_solid_coord(img: &RgbaImage, point: &Point) -> bool {
-	if !is_point_in_image(img, point) {
-        return false
+pub fn get_shapes_layout_by_dynamic_image(img: &DynamicImage, legend: &Legend) -> Result<ShapesLayout> {
+    match img.as_rgba8() {
+        Some(rgba) => get_shapes_layout_by_rgba_image(rgba, legend),
+        None => get_shapes_layout_by_rgba_image(&img.to_rgba(), legend),
     }
+}
 
-	let xy = img.get_pixel(point.x as u32, point.y as u32);
-	is_solid_color(&xy.0)
+/// Layout of an encoded image, its format is guessed from the contents if not given.
+pub fn get_shapes_layout_by_bytes(bytes: &[u8], format: Option<ImageFormat>, legend: &Legend) -> Result<ShapesLayout> {
+    let img = match format {
+        Some(format) => image::load_from_memory_with_format(bytes, format),
+        None => image::load_from_memory(bytes),
+    };
+
+    get_shapes_layout_by_dynamic_image(&img?, legend)
 }
 
-fn is_same_color(img: &RgbaImage, p1: &Point, p2: &Point) -> bool {
-    if !is_point_in_image(img, p1) || !is_point_in_image(img, p2) {
-        return false
-    }
-	img.get_pixel(p1.x as u32, p1.y as u32) == img.get_pixel(p2.x as u32, p2.y as u32)
+/// Layout of an encoded image read from `reader`, its format is guessed from the contents if not given.
+pub fn get_shapes_layout_by_reader<R: Read + Seek>(reader: R, format: Option<ImageFormat>, legend: &Legend) -> Result<ShapesLayout> {
+    let reader = image::io::Reader::new(BufReader::new(reader));
+    let reader = match format {
+        Some(format) => image::io::Reader::with_format(reader.into_inner(), format),
+        None => reader.with_guessed_format()?,
+    };
+
+    get_shapes_layout_by_dynamic_image(&reader.decode()?, legend)
 }
 
-fn process_neighbour(p: &Point, x_diff: i8, y_diff: i8, v: &mut Vec<Point>, img: &RgbaImage) {
-	if let Some(neighbour) = p.get_neighbour(x_diff, y_diff) {
-		if is_same_color(img, &p, &neighbour) {
-			v.push(neighbour);
-		}
-	}
+/// Layout of `img` processed with `legend` settings. The legend is not applied to the layout.
+pub fn get_shapes_layout_by_image(img: &RgbaImage, legend: &Legend) -> Result<ShapesLayout> {
+    let (width, height) = img.dimensions();
+    check_image_dimensions(width, height)?;
+    legend.validate()?;
+
+    let mut warnings = Vec::new();
+    let (img, snap_report, cell_size) = preprocess(img, legend, &mut warnings);
+
+    let regions = Regions::new(&img, legend);
+
+    Ok(ShapesLayout {
+        shapes: get_shapes(&img, legend),
+        color_dependencies: get_color_dependencies(&regions),
+        color_enclosures: get_color_enclosures(&regions),
+        layers: Default::default(),
+        entities: Vec::new(),
+        connections: Vec::new(),
+        image_layers: Vec::new(),
+        width: img.width() as usize,
+        height: img.height() as usize,
+        cell_size: if cell_size > 1 { Some(cell_size) } else { None },
+        warnings,
+        snap_report,
+    })
 }
 
-fn get_neighbours_map(img: &RgbaImage) -> NeighboursMap {
+/// Image the layout of `img` is built from, with one pixel per layout pixel.
+pub fn get_layout_image<'a>(img: &'a RgbaImage, legend: &Legend) -> Result<Cow<'a, RgbaImage>> {
     let (width, height) = img.dimensions();
+    check_image_dimensions(width, height)?;
+    legend.validate()?;
 
-    let mut neighbours = NeighboursMap::new();
+    let (img, _, _) = preprocess(img, legend, &mut Vec::new());
 
-    for y in 0..height {
-        for x in 0..width {
-            let p = Point::new(x, y);
+    Ok(img)
+}
 
-            if is_solid_coord(&img, &p) {
-                
-                let pixel = img.get_pixel(p.x as u32, p.y as u32);
-                let color = Color::new(pixel[0], pixel[1], pixel[2]);
+/// Image with colors snapped to the legend palette and cells downsampled to pixels, as the legend requires.
+fn preprocess<'a>(img: &'a RgbaImage, legend: &Legend, warnings: &mut Vec<Warning>) -> (Cow<'a, RgbaImage>, Option<SnapReport>, u32) {
+    let (mut img, snap_report) = snap(img, legend, warnings);
 
-                neighbours.insert(p, (color, Vec::new()));
+    let cell_size = legend.cell_size.resolve(&img, legend);
+    if cell_size > 1 {
+        img = Cow::Owned(cells::downsample(&img, cell_size, legend, warnings));
+    }
 
-                let v = &mut neighbours.get_mut(&p).unwrap().1;
+    (img, snap_report, cell_size)
+}
 
-                process_neighbour(&p, -1, 0, v, &img);
-                process_neighbour(&p, 1, 0, v, &img);
-                process_neighbour(&p, 0, -1, v, &img);
-                process_neighbour(&p, 0, 1, v, &img);
-            }
-        }
+/// Image with colors snapped to the legend palette, if the legend has snapping.
+pub(crate) fn snap<'a>(img: &'a RgbaImage, legend: &Legend, warnings: &mut Vec<Warning>) -> (Cow<'a, RgbaImage>, Option<SnapReport>) {
+    let mut img = Cow::Borrowed(img);
+
+    let snap_report = legend.snapping.as_ref().map(|snapping| snapping.apply(img.to_mut(), legend));
+
+    if let Some(report) = &snap_report {
+        warnings.extend(report.out_of_tolerance.iter()
+            .map(|(color, points)| Warning::OutOfTolerance { color: *color, point: points[0], count: points.len() }));
     }
 
-    neighbours
+    (img, snap_report)
 }
 
-use std::collections::HashSet;
+/// Every pixel of an image must be addressable by `Point` and get its own component label.
+pub fn check_image_dimensions(width: u32, height: u32) -> Result<()> {
+    let points_count = (width as usize).checked_mul(height as usize);
 
-fn collect_complex_shape(start_point: &Point, neighbours: &NeighboursMap, processed: &mut HashSet<Point>, shape_points: &mut HashSet<Point>) {
-    if !neighbours.contains_key(start_point) {
-        return;
+    match points_count {
+        Some(0) => Err(Error::EmptyImage),
+        Some(count) if count < NO_LABEL as usize => Ok(()),
+        _ => Err(Error::ImageTooLarge { width, height }),
     }
+}
 
-    if processed.contains(start_point) {
-        return;
+fn get_shapes(img: &RgbaImage, legend: &Legend) -> BTreeMap<Color, Vec<Shape>> {
+    let (width, height) = img.dimensions();
+
+    let connectivities = legend.get_connectivities();
+
+    let components = labeling::label_components(width as usize, height as usize, |x, y| get_pixel_color(img, x as u32, y as u32, legend), |color| {
+        connectivities.get(color).cloned().unwrap_or(legend.connectivity)
+    });
+
+    let colors: Vec<Color> = (0 .. components.get_count())
+        .map(|label| {
+            let first = components.get_first_point(label as u32);
+            get_pixel_color(img, first.x as u32, first.y as u32, legend).unwrap()
+        })
+        .collect();
+
+    let component_shapes = get_shapes_by_components(&components, &Point::default(), |label| {
+        let color = &colors[label as usize];
+        (legend.get_decomposition(color).get_strategy(), connectivities.get(color).cloned().unwrap_or(legend.connectivity))
+    });
+
+    let mut shapes: BTreeMap<Color, Vec<Shape>> = BTreeMap::new();
+
+    for (shape, color) in component_shapes.into_iter().zip(colors) {
+        shapes.entry(color).or_default().push(shape);
     }
 
-    processed.insert(*start_point);
-    shape_points.insert(*start_point);
+    shapes
+}
 
-    for near in &neighbours.get(start_point).unwrap().1 {
-        collect_complex_shape(near, neighbours, processed, shape_points);
+/// Connected shapes of a set of points.
+pub fn get_shapes_by_points(points: &HashSet<Point>, strategy: &dyn DecompositionStrategy, connectivity: Connectivity) -> Vec<Shape> {
+    if points.is_empty() {
+        return Vec::new();
     }
+
+    let bbox = calc_bbox_by_points(points);
+
+    let components = labeling::label_components(bbox.get_width(), bbox.get_height(), |x, y| {
+        if points.contains(&Point::new(bbox.min.x + x, bbox.min.y + y)) { Some(()) } else { None }
+    }, |_| connectivity);
+
+    get_shapes_by_components(&components, &bbox.min, |_| (strategy, connectivity))
 }
 
-pub fn get_shapes_layout(img_path: &str) -> Result<ShapesLayout, image::ImageError> {
-    let res = ShapesLayout {
-        shapes: get_shapes(img_path)?,
-        color_dependencies: HashMap::new()
-    };
+/// Shape of every component in labels order, `origin` is the position of the components grid.
+/// `options` gives the decomposition strategy and connectivity of a component by its label.
+fn get_shapes_by_components<'a, F>(components: &Components, origin: &Point, options: F) -> Vec<Shape>
+    where F: Fn(u32) -> (&'a dyn DecompositionStrategy, Connectivity)
+{
+    let stats = components.get_stats();
+
+    let mut complex_points: Vec<Option<HashSet<Point>>> = stats.iter()
+        .map(|(bbox, count)| if *count > 1 && *count < bbox.get_points_count() { Some(HashSet::new()) } else { None })
+        .collect();
+
+    if complex_points.iter().any(Option::is_some) {
+        for y in 0 .. components.height {
+            for x in 0 .. components.width {
+                let label = components.get_label(x, y);
+                if label == NO_LABEL {
+                    continue;
+                }
+
+                if let Some(points) = &mut complex_points[label as usize] {
+                    points.insert(Point::new(origin.x + x, origin.y + y));
+                }
+            }
+        }
+    }
+
+    stats.iter().zip(complex_points).enumerate()
+        .map(|(label, ((bbox, _count), points))| {
+            let bbox = BBox::new_xy(origin.x + bbox.min.x, origin.y + bbox.min.y, origin.x + bbox.max.x, origin.y + bbox.max.y);
+
+            let mut shape = match points {
+                Some(points) => {
+                    let (strategy, connectivity) = options(label as u32);
+                    Shape::Complex(ComplexGeometry::new_with_options(points, strategy, connectivity))
+                },
+                None => Shape::Box(bbox),
+            };
+
+            shape.simplify();
+            shape
+        })
+        .collect()
+}
+
+pub(crate) fn get_pixel_color(img: &RgbaImage, x: u32, y: u32, legend: &Legend) -> Option<Color> {
+    legend.get_pixel_color(&img.get_pixel(x, y).0)
+}
 
-    Ok(res)
+/// 4-connected regions of the whole image. All empty pixels are treated as one color.
+struct Regions
+{
+    components: Components,
+    colors: Vec<Option<Color>>,
+    /// Labels of the regions touching every region by a side.
+    adjacency: Vec<HashSet<usize>>,
 }
 
-fn get_shapes(img_path: &str) -> Result<HashMap<Color, Vec<Shape>>, image::ImageError> {
+impl Regions
+{
+    fn new(img: &RgbaImage, legend: &Legend) -> Self {
+        let (width, height) = img.dimensions();
 
-    let img = image::open(img_path)?.into_rgba();
-    let neighbours = get_neighbours_map(&img);
+        let components = labeling::label_components(width as usize, height as usize, |x, y| Some(get_pixel_color(img, x as u32, y as u32, legend)), |_| Connectivity::Four);
+
+        let colors = (0 .. components.get_count())
+            .map(|label| {
+                let first = components.get_first_point(label as u32);
+                get_pixel_color(img, first.x as u32, first.y as u32, legend)
+            })
+            .collect();
+
+        let adjacency = Self::get_adjacency(&components);
+
+        Self { components, colors, adjacency }
+    }
+
+    fn get_adjacency(components: &Components) -> Vec<HashSet<usize>> {
+        let mut adjacency = vec![HashSet::new(); components.get_count()];
+
+        let (width, height) = (components.width, components.height);
 
-    Ok(get_shapes_by_neighbour_points(neighbours))
+        for y in 0 .. height {
+            for x in 0 .. width {
+                let label = components.get_label(x, y) as usize;
+
+                let right = if x + 1 < width { Some(components.get_label(x + 1, y) as usize) } else { None };
+                let bottom = if y + 1 < height { Some(components.get_label(x, y + 1) as usize) } else { None };
+
+                for other in right.iter().chain(bottom.iter()) {
+                    if *other != label {
+                        adjacency[label].insert(*other);
+                        adjacency[*other].insert(label);
+                    }
+                }
+            }
+        }
+
+        adjacency
+    }
+
+    fn get_border_labels(&self) -> HashSet<usize> {
+        let (width, height) = (self.components.width, self.components.height);
+        let mut border = HashSet::new();
+
+        for x in 0 .. width {
+            border.insert(self.components.get_label(x, 0) as usize);
+            border.insert(self.components.get_label(x, height - 1) as usize);
+        }
+
+        for y in 0 .. height {
+            border.insert(self.components.get_label(0, y) as usize);
+            border.insert(self.components.get_label(width - 1, y) as usize);
+        }
+
+        border
+    }
 }
 
-pub fn get_shapes_by_neighbour_points(neighbours: NeighboursMap) -> HashMap<Color, Vec<Shape>> {
+/// For every color the set of other colors touching it by a side.
+fn get_color_dependencies(regions: &Regions) -> BTreeMap<Color, BTreeSet<Color>> {
+    let mut dependencies: BTreeMap<Color, BTreeSet<Color>> = BTreeMap::new();
 
-    let mut shapes: HashMap<Color, Vec<Shape>> = HashMap::new();
-    let mut processed: HashSet<Point> = HashSet::new();
+    for (label, neighbours) in regions.adjacency.iter().enumerate() {
+        if let Some(color) = regions.colors[label] {
+            for neighbour in neighbours {
+                if let Some(neighbour_color) = regions.colors[*neighbour] {
+                    dependencies.entry(color).or_default().insert(neighbour_color);
+                }
+            }
+        }
+    }
 
-    for p in neighbours.iter() {
-        let (point, nears_info) = p;
-        let (color, _nears) = nears_info;
-        if !processed.contains(point) {
-            let mut geometry_points: HashSet<Point> = HashSet::new();
+    dependencies
+}
 
-            collect_complex_shape(point, &neighbours, &mut processed, &mut geometry_points);
+/// For every color the set of colors it can't leave the image without crossing.
+fn get_color_enclosures(regions: &Regions) -> BTreeMap<Color, BTreeSet<Color>> {
+    let mut enclosures: BTreeMap<Color, BTreeSet<Color>> = BTreeMap::new();
 
-            let mut shape =
-                if geometry_points.len() == 1 {
-                    Shape::Pixel(*geometry_points.iter().next().unwrap())
-                } else if let Some(bbox) = are_points_is_bbox(&geometry_points) {
-                    Shape::Box(bbox)
-                } else {
-                    let complex_geometry = ComplexGeometry::new(geometry_points);
-                    Shape::Complex(complex_geometry)
-                };
+    if regions.colors.is_empty() {
+        return enclosures;
+    }
 
-            shape.simplify();
+    let border = regions.get_border_labels();
+    let colors: BTreeSet<Color> = regions.colors.iter().flatten().cloned().collect();
+
+    for wall in &colors {
+        // flood everything reachable from the image border around the `wall` regions
+        let mut reached = vec![false; regions.colors.len()];
+        let mut stack: Vec<usize> = border.iter().cloned().filter(|l| regions.colors[*l] != Some(*wall)).collect();
+
+        for label in &stack {
+            reached[*label] = true;
+        }
 
-            if !shapes.contains_key(color) {
-                shapes.insert(*color, Vec::new());
+        while let Some(label) = stack.pop() {
+            for neighbour in &regions.adjacency[label] {
+                if !reached[*neighbour] && regions.colors[*neighbour] != Some(*wall) {
+                    reached[*neighbour] = true;
+                    stack.push(*neighbour);
+                }
             }
+        }
+
+        let escaped: HashSet<Color> = reached.iter().enumerate()
+            .filter(|(_, reached)| **reached)
+            .filter_map(|(label, _)| regions.colors[label])
+            .collect();
 
-            shapes.get_mut(color).unwrap().push(shape);
+        for color in &colors {
+            if color != wall && !escaped.contains(color) {
+                enclosures.entry(*color).or_default().insert(*wall);
+            }
         }
     }
 
-    shapes
+    enclosures
 }
 
 fn are_points_is_bbox(points: &HashSet<Point>) -> Option<BBox> {
@@ -167,27 +380,19 @@ fn are_points_is_bbox(points: &HashSet<Point>) -> Option<BBox> {
 }
 
 pub fn calc_bbox_by_points(points: &HashSet<Point>) -> BBox {
+    let mut points = points.iter();
 
-    let mut bbox = BBox::default();
-
-    if points.is_empty() {
-        return bbox;
-    }
-
-    let mut min_x: usize = 9999;
-    let mut max_x: usize = 0;
-    let mut min_y: usize = 9999;
-    let mut max_y: usize = 0;
+    let mut bbox = match points.next() {
+        Some(first) => BBox::new(first, first),
+        None => return BBox::default(),
+    };
 
     for point in points {
-        min_x = min_x.min(point.x);
-        max_x = max_x.max(point.x);
-        min_y = min_y.min(point.y);
-        max_y = max_y.max(point.y);
+        bbox.min.x = bbox.min.x.min(point.x);
+        bbox.max.x = bbox.max.x.max(point.x);
+        bbox.min.y = bbox.min.y.min(point.y);
+        bbox.max.y = bbox.max.y.max(point.y);
     }
 
-    bbox.min = Point::new(min_x, min_y);
-    bbox.max = Point::new(max_x, max_y);
-
     bbox
 }
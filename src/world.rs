@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::connections::Connection;
+use crate::contour::Outline;
+use crate::entities::Entity;
+use crate::error::{Error, Result};
+use crate::json;
+use crate::layered::ImageLayer;
+use crate::types::{
+	BBox,
+	Color,
+	Layer,
+	Point,
+	Shape,
+	ShapesLayout,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldPoint
+{
+	pub x: f64,
+	pub y: f64,
+}
+
+impl WorldPoint
+{
+	pub fn new(x: f64, y: f64) -> Self {
+		Self { x, y }
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldRect
+{
+	pub min: WorldPoint,
+	pub max: WorldPoint,
+}
+
+impl WorldRect
+{
+	pub fn get_center(&self) -> WorldPoint {
+		WorldPoint::new((self.min.x + self.max.x) / 2.0, (self.min.y + self.max.y) / 2.0)
+	}
+
+	pub fn get_width(&self) -> f64 {
+		self.max.x - self.min.x
+	}
+
+	pub fn get_height(&self) -> f64 {
+		self.max.y - self.min.y
+	}
+}
+
+/// Outline in world space. Flipping y reverses the loops: outer boundaries go counter-clockwise in a y-up world.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldPolygon
+{
+	pub boundary: Vec<WorldPoint>,
+	pub holes: Vec<Vec<WorldPoint>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WorldShape
+{
+	/// Position of a single pixel shape, see `PixelAnchor`.
+	Point(WorldPoint),
+	Rect(WorldRect),
+	/// Area of every piece of a complex shape, single pixels included, and the outline of the shape with its holes.
+	Complex {
+		bounds: WorldRect,
+		rects: Vec<WorldRect>,
+		#[serde(default)]
+		polygons: Vec<WorldPolygon>,
+	},
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldEntity
+{
+	pub kind: String,
+	/// Position of the marker top-left pixel, see `PixelAnchor`.
+	pub position: WorldPoint,
+	pub bounds: WorldRect,
+}
+
+/// Which point of a pixel is its position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PixelAnchor
+{
+	/// The corner with the least world coordinates.
+	#[default]
+	Corner,
+	Center,
+}
+
+impl PixelAnchor
+{
+	pub fn from_name(name: &str) -> Option<Self> {
+		match name {
+			"corner" | "Corner" => Some(PixelAnchor::Corner),
+			"center" | "Center" => Some(PixelAnchor::Center),
+			_ => None,
+		}
+	}
+}
+
+/// Mapping of layout pixels into the world space of an engine.
+/// Areas always cover whole pixels, the anchor only moves positions of single pixels.
+///
+/// ```ron
+/// transform: Some((scale: 0.5, origin: (x: -10.0, y: 0.0), flip_y: true, anchor: Center)),
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform
+{
+	/// World units per layout pixel.
+	pub scale: f64,
+	/// World position of the top-left corner of the layout, or of the bottom-left one if y is flipped.
+	pub origin: WorldPoint,
+	/// World y points up.
+	pub flip_y: bool,
+	pub anchor: PixelAnchor,
+}
+
+impl Default for Transform
+{
+	fn default() -> Self {
+		Self {
+			scale: 1.0,
+			origin: WorldPoint::default(),
+			flip_y: false,
+			anchor: PixelAnchor::default(),
+		}
+	}
+}
+
+impl Transform
+{
+	/// World position of the pixel corner `(x, y)` of a layout `height` pixels high.
+	pub fn to_world(&self, x: f64, y: f64, height: usize) -> WorldPoint {
+		let y = if self.flip_y { height as f64 - y } else { y };
+		WorldPoint::new(self.origin.x + x * self.scale, self.origin.y + y * self.scale)
+	}
+
+	pub fn get_rect(&self, bbox: &BBox, height: usize) -> WorldRect {
+		let a = self.to_world(bbox.min.x as f64, bbox.min.y as f64, height);
+		let b = self.to_world(bbox.max.x as f64 + 1.0, bbox.max.y as f64 + 1.0, height);
+
+		WorldRect {
+			min: WorldPoint::new(a.x.min(b.x), a.y.min(b.y)),
+			max: WorldPoint::new(a.x.max(b.x), a.y.max(b.y)),
+		}
+	}
+
+	pub fn get_point(&self, point: &Point, height: usize) -> WorldPoint {
+		let rect = self.get_rect(&BBox::new(point, point), height);
+
+		match self.anchor {
+			PixelAnchor::Corner => rect.min,
+			PixelAnchor::Center => rect.get_center(),
+		}
+	}
+
+	pub fn get_polygon(&self, outline: &Outline, height: usize) -> WorldPolygon {
+		let get_loop = |corners: &Vec<Point>| -> Vec<WorldPoint> {
+			corners.iter().map(|corner| self.to_world(corner.x as f64, corner.y as f64, height)).collect()
+		};
+
+		WorldPolygon {
+			boundary: get_loop(&outline.boundary),
+			holes: outline.holes.iter().map(get_loop).collect(),
+		}
+	}
+
+	pub fn get_shape(&self, shape: &Shape, height: usize) -> WorldShape {
+		match shape {
+			Shape::Pixel(point) => WorldShape::Point(self.get_point(point, height)),
+			Shape::Box(bbox) => WorldShape::Rect(self.get_rect(bbox, height)),
+			Shape::Complex(geom) => WorldShape::Complex {
+				bounds: self.get_rect(geom.get_outer_bbox(), height),
+				rects: geom.get_bboxes().iter().cloned()
+					.chain(geom.get_points().iter().map(|point| BBox::new(point, point)))
+					.map(|bbox| self.get_rect(&bbox, height))
+					.collect(),
+				polygons: shape.get_outlines().iter().map(|outline| self.get_polygon(outline, height)).collect(),
+			},
+		}
+	}
+
+	pub fn get_entity(&self, entity: &Entity, height: usize) -> WorldEntity {
+		WorldEntity {
+			kind: entity.kind.clone(),
+			position: self.get_point(&entity.position, height),
+			bounds: self.get_rect(&entity.bbox, height),
+		}
+	}
+
+	pub fn apply(&self, layout: &ShapesLayout) -> WorldLayout {
+		let get_shapes = |shapes: &BTreeMap<Color, Vec<Shape>>| -> BTreeMap<Color, Vec<WorldShape>> {
+			shapes.iter()
+				.map(|(color, shapes)| (*color, shapes.iter().map(|shape| self.get_shape(shape, layout.height)).collect()))
+				.collect()
+		};
+
+		WorldLayout {
+			shapes: get_shapes(&layout.shapes),
+			layers: layout.layers.clone(),
+			entities: layout.entities.iter().map(|entity| self.get_entity(entity, layout.height)).collect(),
+			connections: layout.connections.clone(),
+			image_layers: layout.image_layers.iter()
+				.map(|layer| ImageLayer {
+					name: layer.name.clone(),
+					shapes: get_shapes(&layer.shapes),
+					connections: layer.connections.clone(),
+					warnings: Vec::new(),
+				})
+				.collect(),
+			width: layout.width as f64 * self.scale,
+			height: layout.height as f64 * self.scale,
+			transform: *self,
+		}
+	}
+}
+
+/// Layout with float world coordinates for the engine, the pixel layout stays for tooling.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldLayout
+{
+	pub shapes: BTreeMap<Color, Vec<WorldShape>>,
+	#[serde(default)]
+	pub layers: BTreeMap<String, Layer>,
+	#[serde(default)]
+	pub entities: Vec<WorldEntity>,
+	/// Shapes are identified by their indices, which are the same as in the pixel layout.
+	#[serde(default)]
+	pub connections: Vec<Connection>,
+	/// Shapes of every image of a layered level, see `ShapesLayout::image_layers`.
+	#[serde(default)]
+	pub image_layers: Vec<ImageLayer<WorldShape>>,
+	pub width: f64,
+	pub height: f64,
+	pub transform: Transform,
+}
+
+impl WorldLayout
+{
+	pub fn to_ron_string(&self) -> Result<String> {
+		ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new()).map_err(Error::Serialize)
+	}
+
+	pub fn write_ron<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+		let ron = self.to_ron_string()?;
+		std::fs::write(path, ron)?;
+
+		Ok(())
+	}
+
+	pub fn from_ron(ron: &str) -> Result<Self> {
+		ron::de::from_str(ron).map_err(Error::Parse)
+	}
+
+	pub fn to_json_string(&self) -> Result<String> {
+		json::to_json_string(self)
+	}
+
+	pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+		let json = self.to_json_string()?;
+		std::fs::write(path, json)?;
+
+		Ok(())
+	}
+
+	pub fn from_json(json: &str) -> Result<Self> {
+		json::from_json(json)
+	}
+
+	/// Shapes of the legend layer `name`, empty if the layer is known but absent in the image.
+	pub fn get_layer_shapes(&self, name: &str) -> Option<&[WorldShape]> {
+		let layer = self.layers.get(name)?;
+
+		Some(self.shapes.get(&layer.color).map(Vec::as_slice).unwrap_or(&[]))
+	}
+
+	pub fn get_image_layer(&self, name: &str) -> Option<&ImageLayer<WorldShape>> {
+		self.image_layers.iter().find(|layer| layer.name == name)
+	}
+
+	/// Shapes of the legend layer `name` in the image layer `image_layer`, see `ShapesLayout::get_image_layer_shapes`.
+	pub fn get_image_layer_shapes(&self, image_layer: &str, name: &str) -> Option<&[WorldShape]> {
+		let layer = self.layers.get(name)?;
+		let image_layer = self.get_image_layer(image_layer)?;
+
+		Some(image_layer.shapes.get(&layer.color).map(Vec::as_slice).unwrap_or(&[]))
+	}
+}